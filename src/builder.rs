@@ -0,0 +1,367 @@
+use std::path::PathBuf;
+
+use fastrand::Rng;
+
+use crate::controller::collect_stats::collect_stats;
+use crate::controller::load_image::load_image;
+use crate::controller::wfc;
+use crate::error::Error;
+use crate::error::Result;
+use crate::model::args::Args;
+use crate::model::cell_selection::CellSelection;
+use crate::model::edge::Edge;
+use crate::model::generation::Generation;
+use crate::model::image::Image;
+use crate::model::image::RawImage;
+use crate::model::option_selection::OptionSelection;
+use crate::model::pattern_data::PatternData;
+use crate::model::pattern_propagator::PatternPropagator;
+use crate::model::pattern_stats::PatternStats;
+use crate::model::sample::Sample;
+use crate::model::solver::Solver;
+use crate::model::symmetry::Symmetry;
+
+enum SampleSource {
+    Path(PathBuf),
+    Image(Image),
+}
+
+impl SampleSource {
+    fn load(self) -> Result<Image> {
+        match self {
+            SampleSource::Path(path) => load_image(path),
+            SampleSource::Image(image) => Ok(image),
+        }
+    }
+}
+
+/// Configures a `Wfc` generator.
+///
+/// ```no_run
+/// use wfc_demo::WfcBuilder;
+///
+/// let generation = WfcBuilder::new()
+///     .sample_path("data/flowers.png")
+///     .pattern_size(3, 3)
+///     .target_size(48, 48)
+///     .seed(7)
+///     .build()?
+///     .run()?;
+/// # Ok::<(), wfc_demo::Error>(())
+/// ```
+pub struct WfcBuilder {
+    samples: Vec<(SampleSource, u32, Option<SampleSource>)>,
+    args: Args,
+    seed: Option<u64>,
+}
+
+impl WfcBuilder {
+    pub fn new() -> Self {
+        Self {
+            samples: Vec::new(),
+            args: Args::default(),
+            seed: None,
+        }
+    }
+
+    /// Adds the image file at `path` to the samples the patterns are extracted from.
+    pub fn sample_path<T: Into<PathBuf>>(self, path: T) -> Self {
+        self.weighted_sample_path(path, 1)
+    }
+
+    /// Adds the image file at `path` as a sample whose pattern weights are multiplied by `weight`.
+    pub fn weighted_sample_path<T: Into<PathBuf>>(mut self, path: T, weight: u32) -> Self {
+        self.samples
+            .push((SampleSource::Path(path.into()), weight, None));
+        self
+    }
+
+    /// Adds the image file at `path` as a weighted sample, skipping all windows which contain
+    /// a pixel marked in the mask image at `mask_path`. Marked pixels are neither black
+    /// nor fully transparent.
+    pub fn masked_sample_path<T: Into<PathBuf>, U: Into<PathBuf>>(
+        mut self,
+        path: T,
+        mask_path: U,
+        weight: u32,
+    ) -> Self {
+        self.samples.push((
+            SampleSource::Path(path.into()),
+            weight,
+            Some(SampleSource::Path(mask_path.into())),
+        ));
+        self
+    }
+
+    /// Adds an image which is already in memory to the samples the patterns are extracted from.
+    pub fn sample_image(self, image: Image) -> Self {
+        self.weighted_sample_image(image, 1)
+    }
+
+    /// Adds an image which is already in memory as a sample whose pattern weights are
+    /// multiplied by `weight`.
+    pub fn weighted_sample_image(mut self, image: Image, weight: u32) -> Self {
+        self.samples
+            .push((SampleSource::Image(image), weight, None));
+        self
+    }
+
+    /// Adds an image which is already in memory as a weighted sample, skipping all windows
+    /// which contain a pixel marked in `mask`, see `masked_sample_path`.
+    pub fn masked_sample_image(mut self, image: Image, mask: Image, weight: u32) -> Self {
+        self.samples.push((
+            SampleSource::Image(image),
+            weight,
+            Some(SampleSource::Image(mask)),
+        ));
+        self
+    }
+
+    pub fn pattern_size(mut self, width: u32, height: u32) -> Self {
+        self.args.pattern_width = width;
+        self.args.pattern_height = height;
+        self
+    }
+
+    /// Additionally extracts the patterns from rotated or reflected variants of the sample.
+    pub fn symmetry(mut self, symmetry: Symmetry) -> Self {
+        self.args.symmetry = symmetry;
+        self
+    }
+
+    /// Treats the sample as a torus if `periodic_input` is set, which is the default.
+    /// Otherwise only patterns fully inside the sample are extracted.
+    pub fn periodic_input(mut self, periodic_input: bool) -> Self {
+        self.args.periodic_input = periodic_input;
+        self
+    }
+
+    /// Skips all windows of the samples which contain a fully transparent pixel, so that
+    /// transparent areas can separate several examples painted on one canvas.
+    pub fn skip_transparent(mut self, skip_transparent: bool) -> Self {
+        self.args.skip_transparent = skip_transparent;
+        self
+    }
+
+    /// Reduces the colors of the samples to at most `palette_size` colors before
+    /// extracting patterns, which helps with photos and anti-aliased art.
+    pub fn quantize(mut self, palette_size: u32) -> Self {
+        self.args.palette_size = Some(palette_size);
+        self
+    }
+
+    pub fn target_size(mut self, width: u32, height: u32) -> Self {
+        self.args.target_image_width = width;
+        self.args.target_image_height = height;
+        self
+    }
+
+    /// Wraps the generated image around its edges, so that it tiles seamlessly.
+    pub fn periodic_output(mut self, periodic_output: bool) -> Self {
+        self.args.periodic_output = periodic_output;
+        self
+    }
+
+    /// Restricts the `edges` of the generated image to the patterns found at the same
+    /// edges of the sample, e.g. the ground of a side view.
+    pub fn anchored_edges(mut self, edges: &[Edge]) -> Self {
+        self.args.anchored_edges = edges.to_vec();
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// How the next wave entry to observe is chosen, the neighborhood of the last
+    /// observation by default.
+    pub fn cell_selection(mut self, cell_selection: CellSelection) -> Self {
+        self.args.cell_selection = cell_selection;
+        self
+    }
+
+    /// How the pattern of an observed wave entry is chosen, randomly by weight by default.
+    pub fn option_selection(mut self, option_selection: OptionSelection) -> Self {
+        self.args.option_selection = option_selection;
+        self
+    }
+
+    /// Scales the pattern weights to `weight^(1 / temperature)` when choosing patterns,
+    /// only used with `OptionSelection::Temperature`. Temperatures below 1 stay closer to
+    /// the sample, temperatures above 1 give more varied images.
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.args.temperature = temperature;
+        self
+    }
+
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.args.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn solver(mut self, solver: Solver) -> Self {
+        self.args.solver = solver;
+        self
+    }
+
+    /// Loads the samples, validates the arguments and builds the pattern propagator.
+    pub fn build(self) -> Result<Wfc> {
+        if self.samples.is_empty() {
+            return Err(Error::InvalidArguments(String::from(
+                "no sample image given",
+            )));
+        }
+
+        let mut samples = Vec::with_capacity(self.samples.len());
+        for (source, weight, mask_source) in self.samples {
+            let mut sample = Sample::with_weight(source.load()?, weight);
+            if let Some(mask_source) = mask_source {
+                sample = sample.with_mask(mask_source.load()?);
+            }
+            samples.push(sample);
+        }
+
+        let propagator = wfc::prepare(samples, &self.args)?;
+
+        Ok(Wfc {
+            args: self.args,
+            propagator,
+            seed: self.seed,
+        })
+    }
+}
+
+impl Default for WfcBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Generates images from the patterns of a sample, see `WfcBuilder`.
+pub struct Wfc {
+    args: Args,
+    propagator: PatternPropagator,
+    seed: Option<u64>,
+}
+
+impl Wfc {
+    /// The patterns extracted from the samples, e.g. to export them with `save_atlas`.
+    pub fn pattern_data(&self) -> &PatternData {
+        &self.propagator.pattern_data
+    }
+
+    /// Statistics about the extracted patterns and their compatibilities, e.g. to save them
+    /// as a JSON report with `save_stats`.
+    pub fn stats(&self) -> PatternStats {
+        collect_stats(&self.propagator)
+    }
+
+    /// Generates a new image with the configured seed, or a random one if none was set.
+    pub fn run(&self) -> Result<Generation> {
+        let seed = self.seed.unwrap_or_else(|| fastrand::u64(..));
+        let image = self.run_with_seed(seed)?;
+        Ok(Generation { image, seed })
+    }
+
+    /// Generates a new image with its own random number generator seeded with `seed`.
+    /// The pattern propagator is shared, so several runs may happen concurrently.
+    pub fn run_with_seed(&self, seed: u64) -> Result<RawImage> {
+        let mut rng = Rng::with_seed(seed);
+        wfc::run(&self.propagator, &self.args, &mut rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::controller::extract_patterns::extract_patterns;
+
+    use super::*;
+
+    fn create_sample() -> Image {
+        Image {
+            width: 4,
+            height: 4,
+            #[rustfmt::skip]
+            data: vec![
+                0, 0, 1, 1,
+                0, 0, 1, 1,
+                2, 2, 3, 3,
+                2, 2, 3, 3,
+            ],
+        }
+    }
+
+    #[test]
+    fn it_reproduces_runs_with_the_same_seed() {
+        let wfc = WfcBuilder::new()
+            .sample_image(create_sample())
+            .pattern_size(2, 2)
+            .target_size(8, 8)
+            .build()
+            .unwrap();
+
+        let (image1, image2) = thread::scope(|s| {
+            let run1 = s.spawn(|| wfc.run_with_seed(7).unwrap());
+            let run2 = s.spawn(|| wfc.run_with_seed(7).unwrap());
+            (run1.join().unwrap(), run2.join().unwrap())
+        });
+
+        assert_eq!(image1, image2);
+    }
+
+    #[test]
+    fn it_reports_the_seed_of_a_run() {
+        let wfc = WfcBuilder::new()
+            .sample_image(create_sample())
+            .pattern_size(2, 2)
+            .target_size(8, 8)
+            .seed(42)
+            .build()
+            .unwrap();
+
+        let generation = wfc.run().unwrap();
+
+        assert_eq!(generation.seed, 42);
+        assert_eq!(generation.image, wfc.run_with_seed(42).unwrap());
+    }
+
+    #[test]
+    fn it_generates_seamlessly_tiling_images() {
+        let sample = create_sample();
+        // the colors of the sample are in the order of their palette indices
+        let patterns: Vec<Vec<u8>> =
+            extract_patterns(&[Sample::new(sample.clone())], 2, 2, Symmetry::None, true)
+                .unwrap()
+                .patterns
+                .into_iter()
+                .map(|p| p.pixels)
+                .collect();
+
+        let wfc = WfcBuilder::new()
+            .sample_image(sample)
+            .pattern_size(2, 2)
+            .target_size(7, 5)
+            .periodic_output(true)
+            .build()
+            .unwrap();
+
+        let (width, height, raw) = wfc.run_with_seed(3).unwrap();
+        let color = |x: u32, y: u32| raw[(4 * ((y % height) * width + x % width)) as usize];
+
+        // every window, including the ones wrapping around the edges, is a pattern of the sample
+        for y in 0..height {
+            for x in 0..width {
+                let window = vec![
+                    color(x, y),
+                    color(x + 1, y),
+                    color(x, y + 1),
+                    color(x + 1, y + 1),
+                ];
+                assert!(patterns.contains(&window));
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::path::PathBuf;
+
+use clap::ArgAction;
+use clap::Parser;
+use log::LevelFilter;
+
+use wfc_demo::model::cell_selection::CellSelection;
+use wfc_demo::model::edge::Edge;
+use wfc_demo::model::option_selection::OptionSelection;
+use wfc_demo::model::solver::Solver;
+use wfc_demo::model::symmetry::Symmetry;
+use wfc_demo::Error;
+use wfc_demo::Result;
+use wfc_demo::WfcBuilder;
+
+/// Generates an image from the patterns of a sample image with the
+/// wave function collapse algorithm.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Paths of the sample images to extract the patterns from
+    #[arg(default_value = "data/flowers.png")]
+    pub inputs: Vec<PathBuf>,
+
+    /// Comma separated multipliers for the pattern weights of each sample, all 1 if omitted
+    #[arg(long, value_delimiter = ',')]
+    pub weights: Vec<u32>,
+
+    /// Comma separated paths of mask images for each sample, windows containing a pixel
+    /// which is neither black nor transparent in the mask are skipped
+    #[arg(long, value_delimiter = ',')]
+    pub masks: Vec<PathBuf>,
+
+    /// Skip windows of the samples which contain a fully transparent pixel
+    #[arg(long)]
+    pub skip_transparent: bool,
+
+    /// Path the generated image is written to
+    #[arg(short, long, default_value = "output.png")]
+    pub output: PathBuf,
+
+    /// Width of the extracted patterns
+    #[arg(long, default_value_t = 3)]
+    pub pattern_width: u32,
+
+    /// Height of the extracted patterns
+    #[arg(long, default_value_t = 3)]
+    pub pattern_height: u32,
+
+    /// Rotations and reflections of the sample to extract patterns from
+    #[arg(long, value_enum, default_value_t = Symmetry::None)]
+    pub symmetry: Symmetry,
+
+    /// Whether the sample tiles, if not only patterns fully inside the sample are extracted
+    #[arg(long, action = ArgAction::Set, default_value_t = true)]
+    pub periodic_input: bool,
+
+    /// Quantize the samples to at most this many colors, between 1 and 256
+    #[arg(long, value_name = "COLORS")]
+    pub quantize: Option<u32>,
+
+    /// Width of the generated image
+    #[arg(long, default_value_t = 32)]
+    pub width: u32,
+
+    /// Height of the generated image
+    #[arg(long, default_value_t = 32)]
+    pub height: u32,
+
+    /// Generate an image which tiles seamlessly
+    #[arg(long)]
+    pub periodic_output: bool,
+
+    /// Edges of the generated image which only use patterns found at the same edges of the sample
+    #[arg(long = "anchor", value_enum, value_delimiter = ',')]
+    pub anchored_edges: Vec<Edge>,
+
+    /// Seed for the random number generator, a random seed is used if omitted
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+
+    /// How the next cell to collapse is chosen
+    #[arg(long, value_enum, default_value_t = CellSelection::Neighborhood)]
+    pub cell_selection: CellSelection,
+
+    /// How the pattern of a collapsed cell is chosen
+    #[arg(long, value_enum, default_value_t = OptionSelection::Weighted)]
+    pub option_selection: OptionSelection,
+
+    /// Temperature of the temperature option selection, below 1 the patterns are chosen
+    /// more like in the sample, above 1 more varied
+    #[arg(long, default_value_t = 1.0)]
+    pub temperature: f64,
+
+    /// Number of attempts before giving up
+    #[arg(short, long, default_value_t = 10)]
+    pub attempts: u32,
+
+    /// How contradictions are resolved
+    #[arg(long, value_enum, default_value_t = Solver::Restart)]
+    pub solver: Solver,
+
+    /// Also write an atlas of all extracted patterns to this path, ordered by weight,
+    /// the weights are written to a text file next to it
+    #[arg(long, value_name = "PATH")]
+    pub atlas: Option<PathBuf>,
+
+    /// Scale of the pattern tiles in the atlas
+    #[arg(long, default_value_t = 8)]
+    pub atlas_scale: u32,
+
+    /// Also write a JSON report with statistics about the extracted patterns to this path
+    #[arg(long, value_name = "PATH")]
+    pub stats: Option<PathBuf>,
+
+    /// Exit after writing the atlas and the statistics without generating an image
+    #[arg(long, conflicts_with = "interactive")]
+    pub no_generate: bool,
+
+    /// Log level, one of off, error, warn, info, debug, trace
+    #[arg(long, default_value_t = LevelFilter::Info)]
+    pub log_level: LevelFilter,
+
+    /// Show the generated image in the terminal instead of writing it to the output path
+    #[arg(short, long)]
+    pub interactive: bool,
+}
+
+impl Cli {
+    pub fn to_builder(&self) -> Result<WfcBuilder> {
+        if !self.weights.is_empty() && self.weights.len() != self.inputs.len() {
+            return Err(Error::InvalidArguments(format!(
+                "got {} weights for {} samples",
+                self.weights.len(),
+                self.inputs.len()
+            )));
+        }
+
+        if !self.masks.is_empty() && self.masks.len() != self.inputs.len() {
+            return Err(Error::InvalidArguments(format!(
+                "got {} masks for {} samples",
+                self.masks.len(),
+                self.inputs.len()
+            )));
+        }
+
+        let mut builder = WfcBuilder::new();
+        for (i, input) in self.inputs.iter().enumerate() {
+            let weight = self.weights.get(i).copied().unwrap_or(1);
+            builder = match self.masks.get(i) {
+                Some(mask) => builder.masked_sample_path(input, mask, weight),
+                None => builder.weighted_sample_path(input, weight),
+            };
+        }
+
+        if let Some(palette_size) = self.quantize {
+            builder = builder.quantize(palette_size);
+        }
+
+        let builder = builder
+            .pattern_size(self.pattern_width, self.pattern_height)
+            .symmetry(self.symmetry)
+            .periodic_input(self.periodic_input)
+            .skip_transparent(self.skip_transparent)
+            .target_size(self.width, self.height)
+            .periodic_output(self.periodic_output)
+            .anchored_edges(&self.anchored_edges)
+            .cell_selection(self.cell_selection)
+            .option_selection(self.option_selection)
+            .temperature(self.temperature)
+            .max_attempts(self.attempts)
+            .solver(self.solver);
+
+        Ok(match self.seed {
+            Some(seed) => builder.seed(seed),
+            None => builder,
+        })
+    }
+}
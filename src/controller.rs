@@ -3,16 +3,25 @@ use term2d::model::image::Image;
 use term2d::model::key::Key;
 use term2d::view::canvas::halfblock::HalfblockCanvas;
 
-use crate::model::args::Args;
+use crate::model::image::RawImage;
 use crate::model::state::State;
 use crate::view::renderer::Renderer;
 
+pub mod backtrack;
 pub mod build_propagator;
+pub mod collect_stats;
+pub mod compress_image;
+pub mod export_atlas;
 pub mod extract_patterns;
 pub mod initialize_wave;
 pub mod load_image;
+pub mod mask_samples;
 pub mod observe;
 pub mod propagate;
+pub mod quantize_samples;
+pub mod save_image;
+pub mod select_cell;
+pub mod select_option;
 pub mod wfc;
 
 pub struct Controller {
@@ -21,16 +30,7 @@ pub struct Controller {
 }
 
 impl Controller {
-    pub fn new() -> Self {
-        let args = Args {
-            path: "data/flowers.png",
-            pattern_width: 3,
-            pattern_height: 3,
-            target_image_width: 100,
-            target_image_height: 100,
-        };
-
-        let img_raw = wfc::run(args);
+    pub fn new(img_raw: RawImage) -> Self {
         let img = Image::from(img_raw);
 
         let renderer = Renderer::new();
@@ -0,0 +1,181 @@
+use crate::model::pattern_propagator::PatternPropagator;
+use crate::model::trail::Trail;
+use crate::model::trail::TrailEntry;
+use crate::model::wave::Contradiction;
+use crate::model::wave::Wave;
+
+use super::propagate::ban;
+use super::propagate::propagate;
+use super::propagate::unban;
+
+/// Resolves a `contradiction` by undoing the wave changes back to the last decision,
+/// banning the pattern chosen there and propagating the ban.
+/// If the ban leads to another contradiction the previous decision is revisited.
+/// Fails with the last contradiction if there are no decisions left to revisit.
+pub fn backtrack(
+    wave: &mut Wave,
+    propagator: &PatternPropagator,
+    trail: &mut Trail,
+    mut contradiction: Contradiction,
+) -> Result<(), Contradiction> {
+    while let Some((index, pattern_index)) = undo_last_decision(wave, propagator, trail) {
+        ban(wave, propagator, trail, index, pattern_index);
+
+        if wave.counts[index] == 0 {
+            contradiction = Contradiction { index };
+            continue;
+        }
+
+        wave.last_index_collapsed = index;
+        match propagate(wave, propagator, trail) {
+            Ok(()) => return Ok(()),
+            Err(c) => contradiction = c,
+        }
+    }
+
+    Err(contradiction)
+}
+
+/// Restores all removals up to and including the last decision and returns the
+/// wave entry index and pattern index of that decision.
+fn undo_last_decision(
+    wave: &mut Wave,
+    propagator: &PatternPropagator,
+    trail: &mut Trail,
+) -> Option<(usize, usize)> {
+    wave.unsupported.clear();
+
+    while let Some(entry) = trail.entries.pop() {
+        match entry {
+            TrailEntry::Decision {
+                index,
+                pattern_index,
+            } => return Some((index, pattern_index)),
+            TrailEntry::Removal {
+                index,
+                pattern_index,
+            } => {
+                unban(wave, propagator, index, pattern_index);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::color_map::ColorMap;
+    use crate::model::pattern_data::Pattern;
+    use crate::model::pattern_data::PatternData;
+
+    use super::*;
+
+    /// 1x1 patterns never overlap, so all patterns are compatible.
+    fn create_unconstrained_propagator() -> PatternPropagator {
+        PatternPropagator {
+            pattern_data: PatternData {
+                color_map: ColorMap::new(),
+                edge_patterns: Default::default(),
+                image_height: 0,
+                image_width: 0,
+                pattern_height: 1,
+                pattern_width: 1,
+                patterns: vec![
+                    Pattern {
+                        pixels: vec![0],
+                        weight: 1,
+                    };
+                    3
+                ],
+            },
+            compatibilities: Vec::new(),
+            supports: Vec::new(),
+            total_weight: 3,
+        }
+    }
+
+    #[test]
+    fn it_undoes_the_last_decision() {
+        let propagator = create_unconstrained_propagator();
+        let mut wave = Wave::from_indices(2, 1, vec![1; 3], &[vec![1], vec![2]]);
+        wave.last_index_collapsed = 1;
+        let mut trail = Trail::new(true);
+        trail.push(TrailEntry::Decision {
+            index: 0,
+            pattern_index: 1,
+        });
+        trail.push(TrailEntry::Removal {
+            index: 0,
+            pattern_index: 0,
+        });
+        trail.push(TrailEntry::Decision {
+            index: 1,
+            pattern_index: 2,
+        });
+        trail.push(TrailEntry::Removal {
+            index: 1,
+            pattern_index: 0,
+        });
+        trail.push(TrailEntry::Removal {
+            index: 1,
+            pattern_index: 1,
+        });
+
+        assert_eq!(
+            undo_last_decision(&mut wave, &propagator, &mut trail),
+            Some((1, 2))
+        );
+        assert_eq!(wave.pattern_indices(0).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(wave.pattern_indices(1).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(trail.entries.len(), 2);
+    }
+
+    #[test]
+    fn it_bans_the_choice_of_the_last_decision() {
+        let propagator = create_unconstrained_propagator();
+        let mut wave = Wave::from_indices(2, 1, vec![1; 3], &[vec![1], vec![2]]);
+        wave.last_index_collapsed = 1;
+        let mut trail = Trail::new(true);
+        trail.push(TrailEntry::Decision {
+            index: 1,
+            pattern_index: 2,
+        });
+        trail.push(TrailEntry::Removal {
+            index: 1,
+            pattern_index: 0,
+        });
+
+        let contradiction = Contradiction { index: 0 };
+        assert_eq!(
+            backtrack(&mut wave, &propagator, &mut trail, contradiction),
+            Ok(())
+        );
+        assert_eq!(wave.pattern_indices(0).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(wave.pattern_indices(1).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(
+            trail.entries,
+            vec![TrailEntry::Removal {
+                index: 1,
+                pattern_index: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn it_fails_when_there_is_no_decision_left() {
+        let propagator = create_unconstrained_propagator();
+        let mut wave = Wave::from_indices(2, 1, vec![1; 3], &[vec![1], vec![]]);
+        let mut trail = Trail::new(true);
+        trail.push(TrailEntry::Decision {
+            index: 0,
+            pattern_index: 1,
+        });
+
+        let contradiction = Contradiction { index: 1 };
+        assert_eq!(
+            backtrack(&mut wave, &propagator, &mut trail, contradiction),
+            Err(Contradiction { index: 0 })
+        );
+    }
+}
@@ -3,8 +3,8 @@ use std::time::Instant;
 use log::info;
 
 use crate::model::pattern_data::PatternData;
-use crate::model::pattern_propagator::PatternPixel;
 use crate::model::pattern_propagator::PatternPropagator;
+use crate::model::pattern_propagator::DIRECTIONS;
 use crate::view::progress_bar::end_progress_bar;
 use crate::view::progress_bar::print_progress_bar;
 use crate::view::progress_bar::start_progress_bar;
@@ -13,90 +13,43 @@ pub fn build_propagator(pattern_data: PatternData) -> PatternPropagator {
     info!("building propagator...");
     let now = Instant::now();
 
-    let propagator = initialize_pixels(pattern_data);
-    let propagator = calculate_propagator_relationships(propagator);
+    let total_weight = pattern_data.patterns.iter().map(|p| p.weight).sum();
+    let compatibilities = calculate_compatibilities(&pattern_data);
 
-    info!("  done, took {} ms", now.elapsed().as_millis());
+    let supports = calculate_supports(&pattern_data, &compatibilities);
 
-    propagator
-}
+    let total_compatibilities: usize = compatibilities.iter().map(|c| c.len()).sum();
 
-fn initialize_pixels(pattern_data: PatternData) -> PatternPropagator {
-    let mut pattern_pixels = Vec::new();
-    let PatternData {
-        ref patterns,
-        image_height,
-        image_width,
-        pattern_width,
-        pattern_height,
-    } = pattern_data;
-    let pattern_size = pattern_width * pattern_height;
-    let total_weight = pattern_size * image_width * image_height;
-
-    for i in 0..patterns.len() {
-        for y in 0..pattern_height {
-            for x in 0..pattern_width {
-                let j = (pattern_width * y + x) as usize;
-                //println!("{:?}", pattern_data.get_pixel_index(i, x, y));
-                pattern_pixels.push(PatternPixel {
-                    color: patterns[i].pixels[j],
-                    colors: patterns[i].pixels.clone(),
-                    relationships: Vec::new(),
-                    weight: patterns[i].weight,
-                    x,
-                    y,
-                });
-            }
-        }
-    }
+    info!("  done, took {} ms", now.elapsed().as_millis());
+    info!("  number of offsets: {}", pattern_data.get_offset_count());
+    info!("  number of compatibilities: {}", total_compatibilities);
 
-    let propagator = PatternPropagator {
+    PatternPropagator {
         pattern_data,
-        pattern_pixels,
+        compatibilities,
+        supports,
         total_weight,
-    };
-
-    propagator
+    }
 }
 
-fn calculate_propagator_relationships(
-    mut pattern_propagator: PatternPropagator,
-) -> PatternPropagator {
-    let pattern_data = &pattern_propagator.pattern_data;
-    let PatternData {
-        ref patterns,
-        pattern_width,
-        pattern_height,
-        ..
-    } = *pattern_data;
-
-    start_progress_bar();
-    for this_pattern_index in 0..patterns.len() {
-        let this_colors = &patterns[this_pattern_index].pixels;
-        for y1 in 0..pattern_height {
-            for x1 in 0..pattern_width {
-                let relationships =
-                    calculate_pixel_relationships(pattern_data, this_colors, x1, y1);
-
-                let pi = pattern_data.get_pixel_index(this_pattern_index, x1, y1);
-
-                pattern_propagator.pattern_pixels[pi].relationships = relationships;
-            }
+/// Counts for each pattern and direction the compatible patterns of the neighbor.
+/// Since compatibilities are symmetric these are exactly the patterns supporting it.
+fn calculate_supports(pattern_data: &PatternData, compatibilities: &[Vec<usize>]) -> Vec<u32> {
+    let mut supports = Vec::with_capacity(pattern_data.patterns.len() * DIRECTIONS.len());
+
+    for pattern_index in 0..pattern_data.patterns.len() {
+        for (dx, dy) in DIRECTIONS {
+            let support = pattern_data
+                .get_compatibility_index(pattern_index, dx, dy)
+                .map_or(0, |ci| compatibilities[ci].len());
+            supports.push(support as u32);
         }
-
-        print_progress_bar(100 * (this_pattern_index + 1) / patterns.len());
     }
-    end_progress_bar();
 
-    pattern_propagator
+    supports
 }
 
-fn calculate_pixel_relationships(
-    pattern_data: &PatternData,
-    this_colors: &Vec<u32>,
-    x1: u32,
-    y1: u32,
-) -> Vec<bool> {
+fn calculate_compatibilities(pattern_data: &PatternData) -> Vec<Vec<usize>> {
     let PatternData {
         ref patterns,
         pattern_width,
@@ -104,66 +57,45 @@ fn calculate_pixel_relationships(
         ..
     } = *pattern_data;
 
-    let w = pattern_width;
-    let h = pattern_height;
-    let s = (pattern_width * pattern_height) as usize;
-    let total_relationships = patterns.len() * s * s;
-    let mut relationships = vec![false; total_relationships];
-
-    for that_pattern_index in 0..patterns.len() {
-        let that_colors = &patterns[that_pattern_index].pixels;
-        for y2 in 0..h {
-            for x2 in 0..w {
-                for v in 0..h {
-                    for u in 0..w {
-                        let tx = u as i32 - x2 as i32;
-                        let ty = v as i32 - y2 as i32;
-
-                        let ri =
-                            pattern_data.get_relationship_index(that_pattern_index, x2, y2, u, v);
-
-                        let inside_x_interval = is_inside_interval_intersection(x1, tx, w);
-                        let inside_y_interval = is_inside_interval_intersection(y1, ty, h);
-
-                        if !inside_x_interval || !inside_y_interval {
-                            relationships[ri] = false;
-                            continue;
-                        }
-
-                        // TODO: speed up with caching or clever index traversion
-                        let intersection_match =
-                            is_intersection_match(this_colors, that_colors, tx, ty, w, h);
+    let w = pattern_width as i32;
+    let h = pattern_height as i32;
+    let mut compatibilities = vec![Vec::new(); patterns.len() * pattern_data.get_offset_count()];
 
-                        relationships[ri] = intersection_match;
+    start_progress_bar();
+    for (this_pattern_index, this_pattern) in patterns.iter().enumerate() {
+        let this_colors = &this_pattern.pixels;
+        for dy in (1 - h)..h {
+            for dx in (1 - w)..w {
+                let ci = pattern_data
+                    .get_compatibility_index(this_pattern_index, dx, dy)
+                    .unwrap();
+
+                for (that_pattern_index, that_pattern) in patterns.iter().enumerate() {
+                    let that_colors = &that_pattern.pixels;
+                    if is_intersection_match(
+                        this_colors,
+                        that_colors,
+                        dx,
+                        dy,
+                        pattern_width,
+                        pattern_height,
+                    ) {
+                        compatibilities[ci].push(that_pattern_index);
                     }
                 }
             }
         }
-    }
 
-    relationships
-}
-
-// TODO: proper rust doc
-// Example:
-// this interval:      | |x| | | |
-// other interval: | | | | | |
-// x = 1
-// other_interval_start = -2
-// width = 5
-// Result: true, as x lies inside the 3 unit wide intersection of both intervals
-fn is_inside_interval_intersection(x: u32, other_interval_start: i32, width: u32) -> bool {
-    if other_interval_start < 0 {
-        return (x as i32) < other_interval_start + width as i32;
-    } else if other_interval_start > 0 {
-        return (x as i32) >= other_interval_start;
+        print_progress_bar(100 * (this_pattern_index + 1) / patterns.len());
     }
-    true
+    end_progress_bar();
+
+    compatibilities
 }
 
 fn is_intersection_match(
-    this_colors: &Vec<u32>,
-    that_colors: &Vec<u32>,
+    this_colors: &[u8],
+    that_colors: &[u8],
     that_pattern_x: i32,
     that_pattern_y: i32,
     pattern_width: u32,
@@ -204,20 +136,24 @@ mod tests {
     use super::*;
     use crate::controller::extract_patterns::extract_patterns;
     use crate::controller::load_image::load_image;
+    use crate::model::sample::Sample;
+    use crate::model::symmetry::Symmetry;
 
     #[test]
-    fn it_initializes_pattern_pixel_weights() {
-        let image = load_image("./data/flowers.png");
-        let pattern_data = extract_patterns(image, 3, 3);
-        let pattern_propagator = initialize_pixels(pattern_data);
-        let pattern_pixels = pattern_propagator.pattern_pixels;
+    fn it_calculates_the_total_weight() {
+        let image = load_image("./data/flowers.png").unwrap();
+        let image_size = image.width * image.height;
+        let pattern_data =
+            extract_patterns(&[Sample::new(image)], 3, 3, Symmetry::None, true).unwrap();
+        let pattern_propagator = build_propagator(pattern_data);
 
         let mut total_weight = 0;
-        for i in 0..pattern_pixels.len() {
-            total_weight += pattern_pixels[i].weight;
+        for pattern in &pattern_propagator.pattern_data.patterns {
+            total_weight += pattern.weight;
         }
 
         assert_eq!(pattern_propagator.total_weight, total_weight);
+        assert_eq!(pattern_propagator.total_weight, image_size);
     }
 
     #[test]
@@ -265,7 +201,14 @@ mod tests {
                 ],
             };
 
-            let pattern_data = extract_patterns(image, pattern_width, pattern_height);
+            let pattern_data = extract_patterns(
+                &[Sample::new(image)],
+                pattern_width,
+                pattern_height,
+                Symmetry::None,
+                true,
+            )
+            .unwrap();
 
             // make sure there are no deduplcations to make calculating the pattern index easier
             assert_eq!(
@@ -277,77 +220,52 @@ mod tests {
         }
 
         #[test]
-        fn it_initializes_a_propagator() {
+        fn it_calculates_the_compatibilities_for_a_propagator() {
             let propagator = build_simple_propagator();
 
-            let pi = propagator.pattern_data.get_pixel_index(10, 0, 0);
-            assert_eq!(propagator.pattern_pixels[pi].color, 10);
-            let pi = propagator.pattern_data.get_pixel_index(10, 1, 0);
-            assert_eq!(propagator.pattern_pixels[pi].color, 11);
-            let pi = propagator.pattern_data.get_pixel_index(10, 2, 0);
-            assert_eq!(propagator.pattern_pixels[pi].color, 8);
-            let pi = propagator.pattern_data.get_pixel_index(10, 0, 1);
-            assert_eq!(propagator.pattern_pixels[pi].color, 2);
-            let pi = propagator.pattern_data.get_pixel_index(10, 1, 1);
-            assert_eq!(propagator.pattern_pixels[pi].color, 3);
-            let pi = propagator.pattern_data.get_pixel_index(10, 2, 1);
-            assert_eq!(propagator.pattern_pixels[pi].color, 0);
-
-            let pi = propagator.pattern_data.get_pixel_index(5, 0, 0);
-            assert_eq!(propagator.pattern_pixels[pi].color, 5);
-            let pi = propagator.pattern_data.get_pixel_index(5, 1, 0);
-            assert_eq!(propagator.pattern_pixels[pi].color, 6);
-            let pi = propagator.pattern_data.get_pixel_index(5, 2, 0);
-            assert_eq!(propagator.pattern_pixels[pi].color, 7);
-            let pi = propagator.pattern_data.get_pixel_index(5, 0, 1);
-            assert_eq!(propagator.pattern_pixels[pi].color, 9);
-            let pi = propagator.pattern_data.get_pixel_index(5, 1, 1);
-            assert_eq!(propagator.pattern_pixels[pi].color, 10);
-            let pi = propagator.pattern_data.get_pixel_index(5, 2, 1);
-            assert_eq!(propagator.pattern_pixels[pi].color, 11);
+            // every pattern is compatible with itself
+            let compatible = propagator.get_compatible_patterns(5, 0, 0).unwrap();
+            assert_eq!(compatible, &[5]);
+
+            let compatible = propagator.get_compatible_patterns(5, 1, 0).unwrap();
+            assert!(compatible.contains(&6));
+            assert!(!compatible.contains(&5));
+
+            let compatible = propagator.get_compatible_patterns(5, -1, 0).unwrap();
+            assert!(compatible.contains(&4));
+
+            let compatible = propagator.get_compatible_patterns(1, 0, 1).unwrap();
+            assert!(compatible.contains(&5));
+
+            let compatible = propagator.get_compatible_patterns(1, -2, 1).unwrap();
+            // note that pattern 1 has a 5 where pattern 5 would put its 7
+            assert!(!compatible.contains(&5));
         }
 
         #[test]
-        fn it_calculates_the_relationships_for_a_propagator() {
+        fn it_allows_all_patterns_where_patterns_do_not_overlap() {
             let propagator = build_simple_propagator();
 
-            let pi = propagator.pattern_data.get_pixel_index(5, 0, 0);
-            let ri = propagator
-                .pattern_data
-                .get_relationship_index(5, 1, 0, 1, 0);
-            assert!(propagator.pattern_pixels[pi].relationships[ri]);
-
-            let pi = propagator.pattern_data.get_pixel_index(5, 0, 0);
-            let ri = propagator
-                .pattern_data
-                .get_relationship_index(6, 0, 0, 1, 0);
-            // note that pixels are NOT adjacent
-            assert!(!propagator.pattern_pixels[pi].relationships[ri]);
-
-            let pi = propagator.pattern_data.get_pixel_index(5, 1, 0);
-            let ri = propagator
-                .pattern_data
-                .get_relationship_index(4, 1, 0, 0, 0);
-            assert!(propagator.pattern_pixels[pi].relationships[ri]);
-
-            let pi = propagator.pattern_data.get_pixel_index(1, 0, 0);
-            let ri = propagator
-                .pattern_data
-                .get_relationship_index(1, 1, 1, 1, 1);
-            assert!(propagator.pattern_pixels[pi].relationships[ri]);
-
-            let pi = propagator.pattern_data.get_pixel_index(1, 1, 1);
-            let ri = propagator
-                .pattern_data
-                .get_relationship_index(5, 2, 0, 2, 1);
-            assert!(propagator.pattern_pixels[pi].relationships[ri]);
-
-            let pi = propagator.pattern_data.get_pixel_index(1, 1, 1);
-            let ri = propagator
-                .pattern_data
-                .get_relationship_index(5, 2, 0, 0, 1);
-            // note that the pattern image does not allow the 6 to be right of the 7
-            assert!(!propagator.pattern_pixels[pi].relationships[ri]);
+            assert_eq!(propagator.get_compatible_patterns(5, 3, 0), None);
+            assert_eq!(propagator.get_compatible_patterns(5, 0, -2), None);
+        }
+
+        #[test]
+        fn it_calculates_symmetric_compatibilities() {
+            let propagator = build_simple_propagator();
+            let pattern_count = propagator.pattern_data.patterns.len();
+
+            for p1 in 0..pattern_count {
+                for dy in -1..2 {
+                    for dx in -2..3 {
+                        let compatible = propagator.get_compatible_patterns(p1, dx, dy).unwrap();
+                        for &p2 in compatible {
+                            let reverse = propagator.get_compatible_patterns(p2, -dx, -dy).unwrap();
+                            assert!(reverse.contains(&p1));
+                        }
+                    }
+                }
+            }
         }
     }
 }
@@ -0,0 +1,234 @@
+use std::fs;
+use std::io;
+use std::mem::size_of;
+use std::path::Path;
+
+use crate::error::Result;
+use crate::model::pattern_data::Pattern;
+use crate::model::pattern_data::PatternData;
+use crate::model::pattern_propagator::PatternPropagator;
+use crate::model::pattern_propagator::DIRECTIONS;
+use crate::model::pattern_stats::OffsetDensity;
+use crate::model::pattern_stats::PatternStats;
+use crate::model::pattern_stats::UnsupportedPattern;
+use crate::model::pattern_stats::WeightDistribution;
+
+/// Writes the `stats` as pretty printed JSON to `path`.
+pub fn save_stats<T: AsRef<Path>>(path: T, stats: &PatternStats) -> Result<()> {
+    let json = serde_json::to_string_pretty(stats).map_err(io::Error::from)?;
+    fs::write(path, json + "\n")?;
+    Ok(())
+}
+
+pub fn collect_stats(propagator: &PatternPropagator) -> PatternStats {
+    let pattern_data = &propagator.pattern_data;
+    let weights = propagator.get_weights();
+    let (entropy, max_entropy) = calculate_entropy(&weights);
+
+    PatternStats {
+        pattern_count: pattern_data.patterns.len(),
+        pattern_width: pattern_data.pattern_width,
+        pattern_height: pattern_data.pattern_height,
+        color_count: pattern_data.color_map.len(),
+        weights: calculate_weight_distribution(weights),
+        entropy,
+        max_entropy,
+        offsets: calculate_offset_densities(propagator),
+        propagator_bytes: estimate_propagator_bytes(propagator),
+        unsupported_patterns: find_unsupported_patterns(propagator),
+    }
+}
+
+fn calculate_weight_distribution(mut weights: Vec<u32>) -> WeightDistribution {
+    weights.sort_unstable_by(|a, b| b.cmp(a));
+
+    let total: u32 = weights.iter().sum();
+    let n = weights.len();
+    let median = match n {
+        0 => 0.0,
+        _ if n % 2 == 1 => weights[n / 2] as f64,
+        _ => (weights[n / 2 - 1] + weights[n / 2]) as f64 / 2.0,
+    };
+
+    WeightDistribution {
+        total,
+        min: weights.last().copied().unwrap_or(0),
+        max: weights.first().copied().unwrap_or(0),
+        mean: if n == 0 { 0.0 } else { total as f64 / n as f64 },
+        median,
+        sorted: weights,
+    }
+}
+
+/// Returns the Shannon entropy of the pattern probabilities given by their weights
+/// and the entropy of equally weighted patterns, both in bits.
+fn calculate_entropy(weights: &[u32]) -> (f64, f64) {
+    let total: u32 = weights.iter().sum();
+    if total == 0 {
+        return (0.0, 0.0);
+    }
+
+    let entropy = weights
+        .iter()
+        .filter(|&&w| w > 0)
+        .map(|&w| {
+            let p = w as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum();
+
+    (entropy, (weights.len() as f64).log2())
+}
+
+/// Counts the compatible pattern pairs at each overlapping offset, row by row.
+fn calculate_offset_densities(propagator: &PatternPropagator) -> Vec<OffsetDensity> {
+    let pattern_data = &propagator.pattern_data;
+    let pattern_count = pattern_data.patterns.len();
+    let w = pattern_data.pattern_width as i32;
+    let h = pattern_data.pattern_height as i32;
+    let mut offsets = Vec::with_capacity(pattern_data.get_offset_count());
+
+    for dy in (1 - h)..h {
+        for dx in (1 - w)..w {
+            let compatibilities: usize = (0..pattern_count)
+                .filter_map(|p| propagator.get_compatible_patterns(p, dx, dy))
+                .map(|patterns| patterns.len())
+                .sum();
+            let pairs = pattern_count * pattern_count;
+
+            offsets.push(OffsetDensity {
+                dx,
+                dy,
+                compatibilities,
+                density: if pairs == 0 {
+                    0.0
+                } else {
+                    compatibilities as f64 / pairs as f64
+                },
+            });
+        }
+    }
+
+    offsets
+}
+
+/// Estimates the heap and inline memory of the propagator, ignoring allocator overhead
+/// and unused capacity.
+fn estimate_propagator_bytes(propagator: &PatternPropagator) -> usize {
+    let PatternPropagator {
+        pattern_data: PatternData { ref patterns, .. },
+        ref compatibilities,
+        ref supports,
+        ..
+    } = *propagator;
+
+    let pattern_bytes: usize = patterns
+        .iter()
+        .map(|pattern| size_of::<Pattern>() + pattern.pixels.len())
+        .sum();
+    let compatibility_bytes: usize = compatibilities
+        .iter()
+        .map(|c| size_of::<Vec<usize>>() + c.len() * size_of::<usize>())
+        .sum();
+
+    size_of::<PatternPropagator>()
+        + pattern_bytes
+        + compatibility_bytes
+        + supports.len() * size_of::<u32>()
+}
+
+/// Lists the patterns which have no compatible pattern at some overlapping neighbor.
+fn find_unsupported_patterns(propagator: &PatternPropagator) -> Vec<UnsupportedPattern> {
+    let patterns = &propagator.pattern_data.patterns;
+
+    (0..patterns.len())
+        .filter_map(|pattern| {
+            let directions: Vec<(i32, i32)> = (0..DIRECTIONS.len())
+                .filter(|&d| {
+                    propagator
+                        .get_neighbor_patterns(pattern, d)
+                        .is_some_and(|neighbors| neighbors.is_empty())
+                })
+                .map(|d| DIRECTIONS[d])
+                .collect();
+
+            (!directions.is_empty()).then(|| UnsupportedPattern {
+                pattern,
+                weight: patterns[pattern].weight,
+                directions,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::controller::build_propagator::build_propagator;
+    use crate::controller::extract_patterns::extract_patterns;
+    use crate::model::image::Image;
+    use crate::model::sample::Sample;
+    use crate::model::symmetry::Symmetry;
+
+    use super::*;
+
+    /// Extracts the patterns `01` with weight 2, `10` and `12` from a non-periodic sample.
+    fn build_row_propagator() -> PatternPropagator {
+        let image = Image {
+            width: 5,
+            height: 1,
+            data: vec![0, 1, 0, 1, 2],
+        };
+
+        build_propagator(
+            extract_patterns(&[Sample::new(image)], 2, 1, Symmetry::None, false).unwrap(),
+        )
+    }
+
+    #[test]
+    fn it_summarizes_the_weights() {
+        let stats = collect_stats(&build_row_propagator());
+
+        assert_eq!(stats.pattern_count, 3);
+        assert_eq!(stats.color_count, 3);
+        assert_eq!(stats.weights.sorted, vec![2, 1, 1]);
+        assert_eq!(stats.weights.total, 4);
+        assert_eq!(stats.weights.min, 1);
+        assert_eq!(stats.weights.max, 2);
+        assert_eq!(stats.weights.median, 1.0);
+        assert!((stats.weights.mean - 4.0 / 3.0).abs() < 1e-9);
+        assert!((stats.entropy - 1.5).abs() < 1e-9);
+        assert!((stats.max_entropy - 3f64.log2()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_calculates_the_compatibility_density_per_offset() {
+        let stats = collect_stats(&build_row_propagator());
+
+        let offsets: Vec<(i32, i32, usize)> = stats
+            .offsets
+            .iter()
+            .map(|o| (o.dx, o.dy, o.compatibilities))
+            .collect();
+        assert_eq!(offsets, vec![(-1, 0, 3), (0, 0, 3), (1, 0, 3)]);
+        assert!((stats.offsets[0].density - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_finds_patterns_without_compatible_neighbors() {
+        let stats = collect_stats(&build_row_propagator());
+
+        assert_eq!(stats.unsupported_patterns.len(), 1);
+        assert_eq!(stats.unsupported_patterns[0].pattern, 2);
+        assert_eq!(stats.unsupported_patterns[0].directions, vec![(1, 0)]);
+    }
+
+    #[test]
+    fn it_serializes_to_json() {
+        let stats = collect_stats(&build_row_propagator());
+        let json: serde_json::Value = serde_json::to_value(&stats).unwrap();
+
+        assert_eq!(json["pattern_count"], 3);
+        assert_eq!(json["unsupported_patterns"][0]["directions"][0][0], 1);
+        assert!(json["propagator_bytes"].as_u64().unwrap() > 0);
+    }
+}
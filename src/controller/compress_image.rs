@@ -0,0 +1,59 @@
+use crate::error::Error;
+use crate::error::Result;
+use crate::model::color_map::ColorMap;
+use crate::model::color_map::CompressedImage;
+use crate::model::color_map::RawRgba;
+use crate::model::image::Image;
+
+/// Replaces the colors of the image by their indices in the `color_map`, adding new colors
+/// to it. Fails with `Error::TooManyColors` if the palette runs out of indices.
+pub fn compress_image(image: &Image, color_map: &mut ColorMap) -> Result<CompressedImage> {
+    let mut compressed = CompressedImage::new(image.width, image.height);
+
+    for (i, &color) in image.data.iter().enumerate() {
+        compressed.data[i] = color_map
+            .get_or_insert_index(RawRgba::from(color))
+            .ok_or(Error::TooManyColors)?;
+    }
+
+    Ok(compressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_compresses_images_with_a_shared_palette() {
+        let mut color_map = ColorMap::new();
+        let image1 = Image {
+            width: 2,
+            height: 1,
+            data: vec![0xff0000ff, 0xffff0000],
+        };
+        let image2 = Image {
+            width: 2,
+            height: 1,
+            data: vec![0xff00ff00, 0xff0000ff],
+        };
+
+        let compressed1 = compress_image(&image1, &mut color_map).unwrap();
+        let compressed2 = compress_image(&image2, &mut color_map).unwrap();
+
+        assert_eq!(compressed1.data, vec![0, 1]);
+        assert_eq!(compressed2.data, vec![2, 0]);
+        assert_eq!(color_map.get_color(1), RawRgba(0, 0, 255, 255));
+    }
+
+    #[test]
+    fn it_fails_to_compress_images_with_too_many_colors() {
+        let image = Image {
+            width: 257,
+            height: 1,
+            data: (0..257).collect(),
+        };
+
+        let result = compress_image(&image, &mut ColorMap::new());
+        assert!(matches!(result, Err(Error::TooManyColors)));
+    }
+}
@@ -0,0 +1,151 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::Result;
+use crate::model::color_map::RawRgba;
+use crate::model::image::RawImage;
+use crate::model::pattern_data::PatternData;
+
+use super::save_image::save_image_raw;
+
+/// Gap between two tiles of the atlas in pixels.
+const TILE_GAP: u32 = 1;
+
+/// Writes the atlas of all patterns to `path` and their weights, in the same order,
+/// to a sidecar text file next to it with the extension `txt`.
+pub fn save_atlas<T: AsRef<Path>>(path: T, pattern_data: &PatternData, scale: u32) -> Result<()> {
+    let path = path.as_ref();
+    save_image_raw(path, &render_atlas(pattern_data, scale))?;
+    fs::write(
+        path.with_extension("txt"),
+        format_atlas_weights(pattern_data),
+    )?;
+    Ok(())
+}
+
+/// Renders every pattern as a tile scaled by `scale` into a square grid, ordered by weight
+/// from the heaviest to the lightest pattern and then row by row.
+pub fn render_atlas(pattern_data: &PatternData, scale: u32) -> RawImage {
+    let PatternData {
+        ref color_map,
+        ref patterns,
+        pattern_width,
+        pattern_height,
+        ..
+    } = *pattern_data;
+
+    let columns = (patterns.len() as f64).sqrt().ceil().max(1.0) as u32;
+    let rows = (patterns.len() as u32).div_ceil(columns).max(1);
+    let tile_width = pattern_width * scale;
+    let tile_height = pattern_height * scale;
+    let width = columns * (tile_width + TILE_GAP) + TILE_GAP;
+    let height = rows * (tile_height + TILE_GAP) + TILE_GAP;
+
+    let mut raw = vec![0; (4 * width * height) as usize];
+
+    for (tile_index, pattern_index) in sort_by_weight(pattern_data).into_iter().enumerate() {
+        let tile_x = TILE_GAP + (tile_index as u32 % columns) * (tile_width + TILE_GAP);
+        let tile_y = TILE_GAP + (tile_index as u32 / columns) * (tile_height + TILE_GAP);
+
+        for y in 0..tile_height {
+            for x in 0..tile_width {
+                let pixel_index = ((y / scale) * pattern_width + x / scale) as usize;
+                let color_index = patterns[pattern_index].pixels[pixel_index];
+                let RawRgba(r, g, b, a) = color_map.get_color(color_index);
+
+                let i = (4 * ((tile_y + y) * width + tile_x + x)) as usize;
+                raw[i..i + 4].copy_from_slice(&[r, g, b, a]);
+            }
+        }
+    }
+
+    (width, height, raw)
+}
+
+/// Lists the pattern index and weight of every tile of the atlas, one tile per line.
+pub fn format_atlas_weights(pattern_data: &PatternData) -> String {
+    let mut text = String::from("# tile pattern weight\n");
+
+    for (tile_index, pattern_index) in sort_by_weight(pattern_data).into_iter().enumerate() {
+        let weight = pattern_data.patterns[pattern_index].weight;
+        text.push_str(&format!("{} {} {}\n", tile_index, pattern_index, weight));
+    }
+
+    text
+}
+
+/// Returns the pattern indices ordered by descending weight, equal weights keep their order.
+fn sort_by_weight(pattern_data: &PatternData) -> Vec<usize> {
+    let patterns = &pattern_data.patterns;
+    let mut indices: Vec<usize> = (0..patterns.len()).collect();
+    indices.sort_by_key(|&i| std::cmp::Reverse(patterns[i].weight));
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::color_map::ColorMap;
+    use crate::model::pattern_data::Pattern;
+
+    use super::*;
+
+    fn create_pattern_data() -> PatternData {
+        let mut color_map = ColorMap::new();
+        color_map.get_or_insert_index(RawRgba(0, 0, 0, 255));
+        color_map.get_or_insert_index(RawRgba(255, 255, 255, 255));
+
+        PatternData {
+            color_map,
+            edge_patterns: Default::default(),
+            image_height: 0,
+            image_width: 0,
+            pattern_height: 1,
+            pattern_width: 2,
+            patterns: vec![
+                Pattern {
+                    pixels: vec![0, 0],
+                    weight: 1,
+                },
+                Pattern {
+                    pixels: vec![1, 0],
+                    weight: 5,
+                },
+                Pattern {
+                    pixels: vec![1, 1],
+                    weight: 1,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn it_renders_the_patterns_ordered_by_weight() {
+        let (width, height, raw) = render_atlas(&create_pattern_data(), 2);
+
+        // 2x2 grid of 4x2 tiles with a gap of 1 pixel
+        assert_eq!((width, height), (11, 7));
+
+        let red_at = |x: u32, y: u32| raw[(4 * (y * width + x)) as usize];
+        let alpha_at = |x: u32, y: u32| raw[(4 * (y * width + x)) as usize + 3];
+
+        // the heaviest pattern comes first
+        assert_eq!(red_at(1, 1), 255);
+        assert_eq!(red_at(2, 2), 255);
+        assert_eq!(red_at(3, 1), 0);
+        assert_eq!(red_at(6, 1), 0);
+        assert_eq!(red_at(1, 4), 255);
+
+        // gaps and the unused tile are transparent
+        assert_eq!(alpha_at(0, 0), 0);
+        assert_eq!(alpha_at(5, 1), 0);
+        assert_eq!(alpha_at(6, 4), 0);
+    }
+
+    #[test]
+    fn it_lists_the_weights_of_the_tiles() {
+        assert_eq!(
+            format_atlas_weights(&create_pattern_data()),
+            "# tile pattern weight\n0 1 5\n1 0 1\n2 2 1\n"
+        );
+    }
+}
@@ -1,71 +1,246 @@
 use std::collections::HashMap;
 use std::time::Instant;
 
+use crate::error::Result;
+use crate::model::color_map::ColorMap;
+use crate::model::color_map::CompressedImage;
+use crate::model::edge::Edge;
 use crate::model::image::Image;
 use crate::model::pattern_data::Pattern;
 use crate::model::pattern_data::PatternData;
+use crate::model::sample::Sample;
+use crate::model::symmetry::Symmetry;
 
 use log::info;
 
-pub fn extract_patterns(image: Image, pattern_width: u32, pattern_height: u32) -> PatternData {
+use super::compress_image::compress_image;
+
+/// Collects all windows of the samples and of their variants given by `symmetry` as unique
+/// patterns. Duplicated windows add the weight of their sample to the pattern weight.
+/// A `periodic` sample is treated as a torus, otherwise only windows fully inside it are used.
+/// Windows touching an edge of a variant are recorded as edge patterns of that edge,
+/// as long as the variant keeps the edge of the sample in place.
+/// Windows containing a pixel marked in the mask of their sample are skipped.
+/// The patterns store palette indices of a color map shared by all samples,
+/// fails with `Error::TooManyColors` if the samples have more than 256 colors.
+pub fn extract_patterns(
+    samples: &[Sample],
+    pattern_width: u32,
+    pattern_height: u32,
+    symmetry: Symmetry,
+    periodic: bool,
+) -> Result<PatternData> {
     info!("extracting patterns...");
     let now = Instant::now();
 
-    let mut pattern_index_map: HashMap<Vec<u32>, usize> = HashMap::new();
+    let mut color_map = ColorMap::new();
+    let mut pattern_index_map: HashMap<Vec<u8>, usize> = HashMap::new();
     let mut patterns: Vec<Pattern> = Vec::new();
-    let Image {
-        width: image_width,
-        height: image_height,
-        data: image_data,
-    } = image;
-
-    for image_y in 0..image_height {
-        for image_x in 0..image_width {
-            let mut pixels = vec![0; (pattern_width * pattern_height) as usize];
-
-            for pattern_y in 0..pattern_height {
-                for pattern_x in 0..pattern_width {
-                    let scan_x = (image_x + pattern_x) % image_width;
-                    let scan_y = (image_y + pattern_y) % image_height;
-                    let image_index = (image_width * scan_y + scan_x) as usize;
-                    let pattern_index = (pattern_width * pattern_y + pattern_x) as usize;
-                    pixels[pattern_index] = image_data[image_index];
-                }
-            }
+    let mut edge_patterns: [Vec<usize>; 4] = Default::default();
+    let variants = symmetry.get_variants();
+    let mut window_weight = 0;
+    let mut masked_windows = 0;
+
+    for sample in samples {
+        let compressed = compress_image(&sample.image, &mut color_map)?;
+        let mask = get_mask(sample);
 
-            if let Some(i) = pattern_index_map.get(&pixels) {
-                patterns[*i].weight += 1;
+        for &(quarter_turns, reflected) in variants {
+            let CompressedImage {
+                width: image_width,
+                height: image_height,
+                data: image_data,
+            } = transform_image(&compressed, quarter_turns, reflected);
+            let mask_data = mask
+                .as_ref()
+                .map(|mask| transform_image(mask, quarter_turns, reflected).data);
+            let kept_edges =
+                Edge::ALL.map(|edge| get_source_edge(edge, quarter_turns, reflected) == edge);
+
+            let (window_columns, window_rows) = if periodic {
+                (image_width, image_height)
             } else {
-                pattern_index_map.insert(pixels.clone(), patterns.len());
-                patterns.push(Pattern { pixels, weight: 1 });
+                (
+                    (image_width + 1).saturating_sub(pattern_width),
+                    (image_height + 1).saturating_sub(pattern_height),
+                )
+            };
+
+            for image_y in 0..window_rows {
+                for image_x in 0..window_columns {
+                    let mut pixels = vec![0; (pattern_width * pattern_height) as usize];
+                    let mut is_masked = false;
+
+                    for pattern_y in 0..pattern_height {
+                        for pattern_x in 0..pattern_width {
+                            let scan_x = (image_x + pattern_x) % image_width;
+                            let scan_y = (image_y + pattern_y) % image_height;
+                            let image_index = (image_width * scan_y + scan_x) as usize;
+                            let pattern_index = (pattern_width * pattern_y + pattern_x) as usize;
+                            pixels[pattern_index] = image_data[image_index];
+                            is_masked |= mask_data.as_ref().is_some_and(|m| m[image_index] != 0);
+                        }
+                    }
+
+                    if is_masked {
+                        masked_windows += 1;
+                        continue;
+                    }
+                    window_weight += sample.weight;
+
+                    let i = if let Some(&i) = pattern_index_map.get(&pixels) {
+                        patterns[i].weight += sample.weight;
+                        i
+                    } else {
+                        pattern_index_map.insert(pixels.clone(), patterns.len());
+                        patterns.push(Pattern {
+                            pixels,
+                            weight: sample.weight,
+                        });
+                        patterns.len() - 1
+                    };
+
+                    let is_inside = image_x + pattern_width <= image_width
+                        && image_y + pattern_height <= image_height;
+                    let touched_edges = [
+                        image_y == 0,
+                        image_y + pattern_height == image_height,
+                        image_x == 0,
+                        image_x + pattern_width == image_width,
+                    ];
+                    for edge in Edge::ALL {
+                        let edge_patterns = &mut edge_patterns[edge as usize];
+                        if is_inside
+                            && touched_edges[edge as usize]
+                            && kept_edges[edge as usize]
+                            && !edge_patterns.contains(&i)
+                        {
+                            edge_patterns.push(i);
+                        }
+                    }
+                }
             }
         }
     }
 
-    let mut weight_sum = 0;
-    for i in 0..patterns.len() {
-        weight_sum += patterns[i].weight;
-    }
+    let weight_sum: u32 = patterns.iter().map(|pattern| pattern.weight).sum();
+
+    let (image_width, image_height) = samples
+        .first()
+        .map_or((0, 0), |sample| (sample.image.width, sample.image.height));
 
     info!("  done, took {} ms", now.elapsed().as_millis());
     info!("  pattern width: {}", pattern_width);
     info!("  pattern height: {}", pattern_height);
-    info!("  image width: {}", image_width);
-    info!("  image height: {}", image_height);
+    for sample in samples {
+        info!(
+            "  sample: {}x{} with weight {}",
+            sample.image.width, sample.image.height, sample.weight
+        );
+    }
+    info!("  periodic: {}", periodic);
+    info!("  number of variants: {}", variants.len());
+    info!("  number of colors: {}", color_map.len());
+    info!("  number of masked windows: {}", masked_windows);
     info!("  number of unique patterns: {}", patterns.len());
     info!(
-        "  sum of pattern weights: {} (should equal image_w * image_h)",
-        weight_sum
+        "  sum of pattern weights: {} (should equal the weighted number of windows: {})",
+        weight_sum, window_weight
     );
 
-    PatternData {
+    Ok(PatternData {
+        color_map,
+        edge_patterns,
         patterns,
         // TODO: is this additional data even needed?
         image_height,
         image_width,
         pattern_height,
         pattern_width,
+    })
+}
+
+/// Returns an image of the sample size which is 1 for every masked pixel and 0 otherwise,
+/// so that it can be transformed like the sample, or `None` if the sample has no mask.
+fn get_mask(sample: &Sample) -> Option<CompressedImage> {
+    sample.mask.as_ref()?;
+
+    let Image { width, height, .. } = sample.image;
+    let mut mask = CompressedImage::new(width, height);
+    for (i, masked) in mask.data.iter_mut().enumerate() {
+        *masked = sample.is_masked(i) as u8;
+    }
+
+    Some(mask)
+}
+
+/// Returns the edge of the sample which ends up at `edge` after `transform_image`.
+fn get_source_edge(edge: Edge, quarter_turns: u32, reflected: bool) -> Edge {
+    let mut source_edge = edge;
+
+    // a clockwise quarter turn moves the left edge to the top, the top edge to the right, ...
+    for _ in 0..quarter_turns % 4 {
+        source_edge = match source_edge {
+            Edge::Top => Edge::Left,
+            Edge::Right => Edge::Top,
+            Edge::Bottom => Edge::Right,
+            Edge::Left => Edge::Bottom,
+        };
+    }
+
+    if reflected {
+        source_edge = match source_edge {
+            Edge::Left => Edge::Right,
+            Edge::Right => Edge::Left,
+            _ => source_edge,
+        };
     }
+
+    source_edge
+}
+
+/// Reflects the image horizontally if `reflected` is set, then rotates it clockwise
+/// by `quarter_turns` times 90 degrees.
+fn transform_image(
+    image: &CompressedImage,
+    quarter_turns: u32,
+    reflected: bool,
+) -> CompressedImage {
+    let mut transformed = image.clone();
+
+    if reflected {
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let source_index = (image.width * y + image.width - 1 - x) as usize;
+                transformed.data[(image.width * y + x) as usize] = image.data[source_index];
+            }
+        }
+    }
+
+    for _ in 0..quarter_turns {
+        let CompressedImage {
+            width,
+            height,
+            ref data,
+        } = transformed;
+        let mut rotated = vec![0; data.len()];
+
+        // the rotated image is `height` pixels wide
+        for y in 0..width {
+            for x in 0..height {
+                let source_index = (width * (height - 1 - x) + y) as usize;
+                rotated[(height * y + x) as usize] = data[source_index];
+            }
+        }
+
+        transformed = CompressedImage {
+            width: height,
+            height: width,
+            data: rotated,
+        };
+    }
+
+    transformed
 }
 
 #[cfg(test)]
@@ -76,9 +251,10 @@ mod tests {
 
     #[test]
     fn it_extracts_patterns() {
-        let image = load_image("./data/flowers.png");
+        let image = load_image("./data/flowers.png").unwrap();
         let image_size = image.width * image.height;
-        let pattern_data = extract_patterns(image, 3, 3);
+        let pattern_data =
+            extract_patterns(&[Sample::new(image)], 3, 3, Symmetry::None, true).unwrap();
         let total_unique_patterns = 92;
 
         let mut weight_sum = 0;
@@ -105,7 +281,14 @@ mod tests {
             ],
         };
 
-        let pattern_data = extract_patterns(image, pattern_width, pattern_height);
+        let pattern_data = extract_patterns(
+            &[Sample::new(image)],
+            pattern_width,
+            pattern_height,
+            Symmetry::None,
+            true,
+        )
+        .unwrap();
 
         assert_eq!(pattern_data.patterns[0].pixels, vec![0, 1, 2, 4, 5, 6]);
         assert_eq!(pattern_data.patterns[1].pixels, vec![1, 2, 3, 5, 6, 7]);
@@ -138,7 +321,14 @@ mod tests {
             ],
         };
 
-        let pattern_data = extract_patterns(image, pattern_width, pattern_height);
+        let pattern_data = extract_patterns(
+            &[Sample::new(image)],
+            pattern_width,
+            pattern_height,
+            Symmetry::None,
+            true,
+        )
+        .unwrap();
         assert_eq!(pattern_data.patterns.len(), 12);
         for i in 0..pattern_data.patterns.len() {
             assert_eq!(pattern_data.patterns[i].weight, 1);
@@ -160,7 +350,14 @@ mod tests {
             ],
         };
 
-        let pattern_data = extract_patterns(image, pattern_width, pattern_height);
+        let pattern_data = extract_patterns(
+            &[Sample::new(image)],
+            pattern_width,
+            pattern_height,
+            Symmetry::None,
+            true,
+        )
+        .unwrap();
         assert_eq!(pattern_data.patterns.len(), 1);
         for i in 0..pattern_data.patterns.len() {
             assert_eq!(pattern_data.patterns[i].weight, 12);
@@ -182,10 +379,382 @@ mod tests {
             ],
         };
 
-        let pattern_data = extract_patterns(image, pattern_width, pattern_height);
+        let pattern_data = extract_patterns(
+            &[Sample::new(image)],
+            pattern_width,
+            pattern_height,
+            Symmetry::None,
+            true,
+        )
+        .unwrap();
         assert_eq!(pattern_data.patterns.len(), 6);
         for i in 0..pattern_data.patterns.len() {
             assert_eq!(pattern_data.patterns[i].weight, 2);
         }
     }
+
+    #[test]
+    fn it_extracts_non_periodic_patterns() {
+        let image = load_image("./data/flowers.png").unwrap();
+        let window_count = (image.width - 2) * (image.height - 2);
+        let pattern_data =
+            extract_patterns(&[Sample::new(image)], 3, 3, Symmetry::None, false).unwrap();
+        let total_unique_patterns = 84;
+
+        let mut weight_sum = 0;
+        for i in 0..pattern_data.patterns.len() {
+            weight_sum += pattern_data.patterns[i].weight;
+        }
+
+        assert_eq!(pattern_data.patterns.len(), total_unique_patterns);
+        assert_eq!(weight_sum, window_count);
+    }
+
+    #[test]
+    fn it_extracts_non_periodic_patterns_in_proper_order() {
+        let pattern_width = 3;
+        let pattern_height = 2;
+        let image = Image {
+            width: 4,
+            height: 3,
+            #[rustfmt::skip]
+            data: vec![
+                0, 1, 2, 3,
+                4, 5, 6, 7,
+                8, 9, 10, 11,
+            ],
+        };
+
+        let pattern_data = extract_patterns(
+            &[Sample::new(image)],
+            pattern_width,
+            pattern_height,
+            Symmetry::None,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(pattern_data.patterns.len(), 4);
+        assert_eq!(pattern_data.patterns[0].pixels, vec![0, 1, 2, 4, 5, 6]);
+        assert_eq!(pattern_data.patterns[1].pixels, vec![1, 2, 3, 5, 6, 7]);
+        assert_eq!(pattern_data.patterns[2].pixels, vec![4, 5, 6, 8, 9, 10]);
+        assert_eq!(pattern_data.patterns[3].pixels, vec![5, 6, 7, 9, 10, 11]);
+    }
+
+    #[test]
+    fn it_extracts_non_periodic_patterns_without_duplicates() {
+        let pattern_width = 3;
+        let pattern_height = 2;
+        let image = Image {
+            width: 4,
+            height: 3,
+            #[rustfmt::skip]
+            data: vec![
+                0, 1, 2, 3,
+                4, 5, 6, 7,
+                8, 9, 10, 11,
+            ],
+        };
+
+        let pattern_data = extract_patterns(
+            &[Sample::new(image)],
+            pattern_width,
+            pattern_height,
+            Symmetry::None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(pattern_data.patterns.len(), 4);
+        for i in 0..pattern_data.patterns.len() {
+            assert_eq!(pattern_data.patterns[i].weight, 1);
+        }
+    }
+
+    #[test]
+    fn it_extracts_non_periodic_patterns_with_all_duplicates() {
+        let pattern_width = 3;
+        let pattern_height = 2;
+        let image = Image {
+            width: 4,
+            height: 3,
+            #[rustfmt::skip]
+            data: vec![
+                0, 0, 0, 0,
+                0, 0, 0, 0,
+                0, 0, 0, 0,
+            ],
+        };
+
+        let pattern_data = extract_patterns(
+            &[Sample::new(image)],
+            pattern_width,
+            pattern_height,
+            Symmetry::None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(pattern_data.patterns.len(), 1);
+        for i in 0..pattern_data.patterns.len() {
+            assert_eq!(pattern_data.patterns[i].weight, 4);
+        }
+    }
+
+    #[test]
+    fn it_extracts_non_periodic_patterns_with_half_duplicates() {
+        let pattern_width = 2;
+        let pattern_height = 2;
+        let image = Image {
+            width: 5,
+            height: 2,
+            #[rustfmt::skip]
+            data: vec![
+                0, 1, 0, 1, 0,
+                2, 3, 2, 3, 2,
+            ],
+        };
+
+        let pattern_data = extract_patterns(
+            &[Sample::new(image)],
+            pattern_width,
+            pattern_height,
+            Symmetry::None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(pattern_data.patterns.len(), 2);
+        for i in 0..pattern_data.patterns.len() {
+            assert_eq!(pattern_data.patterns[i].weight, 2);
+        }
+    }
+
+    #[test]
+    fn it_extracts_patterns_from_multiple_samples() {
+        let plain = Image {
+            width: 2,
+            height: 2,
+            data: vec![0; 4],
+        };
+        let striped = Image {
+            width: 2,
+            height: 2,
+            #[rustfmt::skip]
+            data: vec![
+                0, 0,
+                1, 1,
+            ],
+        };
+
+        let samples = [
+            Sample::with_weight(plain.clone(), 3),
+            Sample::new(striped),
+            Sample::new(plain),
+        ];
+        let pattern_data = extract_patterns(&samples, 2, 2, Symmetry::None, true).unwrap();
+
+        assert_eq!(pattern_data.patterns.len(), 3);
+        assert_eq!(pattern_data.patterns[0].pixels, vec![0, 0, 0, 0]);
+        assert_eq!(pattern_data.patterns[0].weight, 16);
+        assert_eq!(pattern_data.patterns[1].pixels, vec![0, 0, 1, 1]);
+        assert_eq!(pattern_data.patterns[1].weight, 2);
+        assert_eq!(pattern_data.patterns[2].pixels, vec![1, 1, 0, 0]);
+        assert_eq!(pattern_data.patterns[2].weight, 2);
+    }
+
+    #[test]
+    fn it_records_the_patterns_touching_the_edges() {
+        let image = Image {
+            width: 4,
+            height: 3,
+            #[rustfmt::skip]
+            data: vec![
+                0, 1, 2, 3,
+                4, 5, 6, 7,
+                8, 9, 10, 11,
+            ],
+        };
+
+        // the patterns wrapping around the sample do not touch its edges
+        let pattern_data =
+            extract_patterns(&[Sample::new(image)], 3, 2, Symmetry::None, true).unwrap();
+        assert_eq!(pattern_data.get_edge_patterns(Edge::Top), &[0, 1]);
+        assert_eq!(pattern_data.get_edge_patterns(Edge::Bottom), &[4, 5]);
+        assert_eq!(pattern_data.get_edge_patterns(Edge::Left), &[0, 4]);
+        assert_eq!(pattern_data.get_edge_patterns(Edge::Right), &[1, 5]);
+    }
+
+    #[test]
+    fn it_records_only_the_edges_kept_in_place_by_the_variants() {
+        let image = Image {
+            width: 3,
+            height: 3,
+            #[rustfmt::skip]
+            data: vec![
+                0, 0, 0,
+                1, 2, 1,
+                3, 3, 3,
+            ],
+        };
+
+        // the rotated variants move the top and the sides of the sample to its bottom
+        let pattern_data =
+            extract_patterns(&[Sample::new(image)], 1, 1, Symmetry::Dihedral, false).unwrap();
+        let edge_pixels = |edge| -> Vec<Vec<u8>> {
+            pattern_data
+                .get_edge_patterns(edge)
+                .iter()
+                .map(|&i| pattern_data.patterns[i].pixels.clone())
+                .collect()
+        };
+        assert_eq!(edge_pixels(Edge::Top), vec![vec![0]]);
+        assert_eq!(edge_pixels(Edge::Bottom), vec![vec![3]]);
+        assert_eq!(edge_pixels(Edge::Left), vec![vec![0], vec![1], vec![3]]);
+        assert_eq!(edge_pixels(Edge::Right), vec![vec![0], vec![1], vec![3]]);
+    }
+
+    #[test]
+    fn it_finds_the_source_edges_of_the_variants() {
+        assert_eq!(get_source_edge(Edge::Top, 1, false), Edge::Left);
+        assert_eq!(get_source_edge(Edge::Bottom, 2, false), Edge::Top);
+        assert_eq!(get_source_edge(Edge::Left, 0, true), Edge::Right);
+        // a reflection followed by a half turn flips the sample vertically
+        assert_eq!(get_source_edge(Edge::Left, 2, true), Edge::Left);
+        assert_eq!(get_source_edge(Edge::Top, 2, true), Edge::Bottom);
+    }
+
+    #[test]
+    fn it_rotates_and_reflects_images() {
+        let image = CompressedImage {
+            width: 3,
+            height: 2,
+            #[rustfmt::skip]
+            data: vec![
+                0, 1, 2,
+                3, 4, 5,
+            ],
+        };
+
+        let rotated = transform_image(&image, 1, false);
+        assert_eq!((rotated.width, rotated.height), (2, 3));
+        #[rustfmt::skip]
+        assert_eq!(rotated.data, vec![
+            3, 0,
+            4, 1,
+            5, 2,
+        ]);
+
+        let reflected = transform_image(&image, 0, true);
+        #[rustfmt::skip]
+        assert_eq!(reflected.data, vec![
+            2, 1, 0,
+            5, 4, 3,
+        ]);
+
+        let reflected_and_rotated = transform_image(&image, 1, true);
+        #[rustfmt::skip]
+        assert_eq!(reflected_and_rotated.data, vec![
+            5, 2,
+            4, 1,
+            3, 0,
+        ]);
+
+        assert_eq!(transform_image(&image, 4, false).data, image.data);
+    }
+
+    #[test]
+    fn it_extracts_patterns_of_all_variants() {
+        let image = Image {
+            width: 2,
+            height: 2,
+            #[rustfmt::skip]
+            data: vec![
+                0, 1,
+                0, 1,
+            ],
+        };
+
+        // the vertical stripes of the sample are horizontal after a quarter turn
+        let pattern_data = extract_patterns(
+            &[Sample::new(image.clone())],
+            2,
+            2,
+            Symmetry::Rotation,
+            true,
+        )
+        .unwrap();
+        assert_eq!(pattern_data.patterns.len(), 4);
+        assert_eq!(pattern_data.patterns[0].pixels, vec![0, 1, 0, 1]);
+        assert_eq!(pattern_data.patterns[0].weight, 4);
+        assert!(pattern_data
+            .patterns
+            .iter()
+            .any(|p| p.pixels == vec![0, 0, 1, 1]));
+
+        let weight_sum: u32 = pattern_data.patterns.iter().map(|p| p.weight).sum();
+        assert_eq!(weight_sum, 16);
+
+        // the reflection of the sample only shifts the stripes, which the torus already contains
+        let pattern_data =
+            extract_patterns(&[Sample::new(image)], 2, 2, Symmetry::Reflection, true).unwrap();
+        assert_eq!(pattern_data.patterns.len(), 2);
+        assert_eq!(pattern_data.patterns[0].weight, 4);
+    }
+
+    #[test]
+    fn it_skips_windows_with_masked_pixels() {
+        let image = Image {
+            width: 5,
+            height: 1,
+            data: vec![1, 2, 0, 3, 4],
+        };
+        let mask = Image {
+            width: 5,
+            height: 1,
+            data: vec![0, 0, 0xffffffff, 0, 0],
+        };
+
+        let pattern_data = extract_patterns(
+            &[Sample::new(image).with_mask(mask)],
+            2,
+            1,
+            Symmetry::None,
+            false,
+        )
+        .unwrap();
+
+        let patterns: Vec<Vec<u8>> = pattern_data
+            .patterns
+            .iter()
+            .map(|p| p.pixels.clone())
+            .collect();
+        assert_eq!(patterns, vec![vec![0, 1], vec![3, 4]]);
+        assert!(pattern_data.patterns.iter().all(|p| p.weight == 1));
+    }
+
+    #[test]
+    fn it_transforms_the_mask_with_the_sample() {
+        let image = Image {
+            width: 3,
+            height: 3,
+            data: vec![1, 2, 3, 4, 5, 6, 7, 8, 9],
+        };
+        let mut mask = Image {
+            width: 3,
+            height: 3,
+            data: vec![0; 9],
+        };
+        mask.data[0] = 0xffffffff;
+
+        let pattern_data = extract_patterns(
+            &[Sample::new(image).with_mask(mask)],
+            1,
+            1,
+            Symmetry::Dihedral,
+            true,
+        )
+        .unwrap();
+
+        // the masked pixel is skipped in all 8 variants
+        assert_eq!(pattern_data.patterns.len(), 8);
+        assert!(pattern_data.patterns.iter().all(|p| p.weight == 8));
+    }
 }
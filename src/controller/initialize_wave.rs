@@ -1,41 +1,129 @@
 use std::time::Instant;
 
+use fastrand::Rng;
 use log::info;
 
+use crate::model::edge::Edge;
 use crate::model::pattern_propagator::PatternPropagator;
+use crate::model::pattern_propagator::DIRECTIONS;
+use crate::model::trail::Trail;
+use crate::model::wave::Contradiction;
 use crate::model::wave::Wave;
 
+use super::propagate::ban;
+use super::propagate::propagate;
+
+/// Creates a wave for an image of the target size where every entry contains all patterns.
+/// A wave which is not `periodic` has one entry per pattern fully inside the image,
+/// the remaining pixels are taken from the patterns at the right and bottom edges.
+/// The entries at the `anchored_edges` only keep the patterns touching the same sample edge,
+/// fails with a `Contradiction` if these restrictions can't be satisfied.
 pub fn initialize_wave(
     pattern_propagator: &PatternPropagator,
     target_image_width: u32,
     target_image_height: u32,
-) -> Wave {
+    periodic: bool,
+    anchored_edges: &[Edge],
+    rng: &mut Rng,
+) -> Result<Wave, Contradiction> {
     info!("initializing wave...");
     let now = Instant::now();
 
-    let mut pixel_indices = Vec::new();
-    for i in 0..pattern_propagator.pattern_pixels.len() {
-        pixel_indices.push(i);
+    let (wave_width, wave_height) = if periodic {
+        (target_image_width, target_image_height)
+    } else {
+        let pattern_data = &pattern_propagator.pattern_data;
+        (
+            target_image_width + 1 - pattern_data.pattern_width,
+            target_image_height + 1 - pattern_data.pattern_height,
+        )
+    };
+
+    let weights = pattern_propagator.get_weights();
+    let mut wave = Wave::new(wave_width, wave_height, weights);
+    wave.periodic = periodic;
+    wave.supports = pattern_propagator.supports.repeat(wave.len());
+    wave.last_index_collapsed = rng.usize(..wave.len());
+    wave.add_entropy_noise(rng);
+
+    queue_unsupported_patterns(&mut wave, pattern_propagator);
+    for &edge in anchored_edges {
+        anchor_edge(&mut wave, pattern_propagator, edge);
     }
-    let indices = vec![pixel_indices; (target_image_width * target_image_height) as usize];
-    let last_index_collapsed = fastrand::usize(..indices.len());
+    propagate(&mut wave, pattern_propagator, &mut Trail::new(false))?;
 
     info!("  done, took {} ms", now.elapsed().as_millis());
-    info!("  wave width: {}", target_image_width);
-    info!("  wave height: {}", target_image_height);
-
-    Wave {
-        width: target_image_width,
-        height: target_image_height,
-        indices,
-        last_index_collapsed,
+    info!("  wave width: {}", wave.width);
+    info!("  wave height: {}", wave.height);
+    info!("  periodic: {}", periodic);
+    info!("  anchored edges: {:?}", anchored_edges);
+
+    Ok(wave)
+}
+
+/// Queues the patterns which have no support at all from a neighbor,
+/// e.g. the patterns at the bottom of a sample which is not periodic.
+fn queue_unsupported_patterns(wave: &mut Wave, propagator: &PatternPropagator) {
+    for pattern_index in 0..propagator.pattern_data.patterns.len() {
+        for direction in 0..DIRECTIONS.len() {
+            if propagator
+                .get_neighbor_patterns(pattern_index, direction)
+                .is_none()
+            {
+                continue;
+            }
+
+            if propagator.supports[pattern_index * DIRECTIONS.len() + direction] > 0 {
+                continue;
+            }
+
+            for index in 0..wave.len() {
+                if wave.get_neighbor(index, direction).is_some() {
+                    wave.unsupported.push((index, pattern_index));
+                }
+            }
+        }
+    }
+}
+
+/// Bans all patterns from the wave entries at `edge` which don't touch the same edge of the sample.
+fn anchor_edge(wave: &mut Wave, propagator: &PatternPropagator, edge: Edge) {
+    let width = wave.width as usize;
+    let height = wave.height as usize;
+    let edge_patterns = propagator.pattern_data.get_edge_patterns(edge);
+
+    let indices: Vec<usize> = match edge {
+        Edge::Top => (0..width).collect(),
+        Edge::Bottom => ((height - 1) * width..height * width).collect(),
+        Edge::Left => (0..height).map(|y| y * width).collect(),
+        Edge::Right => (0..height).map(|y| y * width + width - 1).collect(),
+    };
+
+    for index in indices {
+        for pattern_index in 0..propagator.pattern_data.patterns.len() {
+            if !edge_patterns.contains(&pattern_index) {
+                ban(
+                    wave,
+                    propagator,
+                    &mut Trail::new(false),
+                    index,
+                    pattern_index,
+                );
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::controller::build_propagator::build_propagator;
+    use crate::controller::extract_patterns::extract_patterns;
+    use crate::model::color_map::ColorMap;
+    use crate::model::image::Image;
+    use crate::model::pattern_data::Pattern;
     use crate::model::pattern_data::PatternData;
-    use crate::model::pattern_propagator::PatternPixel;
+    use crate::model::sample::Sample;
+    use crate::model::symmetry::Symmetry;
 
     use super::*;
 
@@ -43,38 +131,161 @@ mod tests {
     fn it_initializes_the_wave() {
         let propagator = PatternPropagator {
             pattern_data: PatternData {
+                color_map: ColorMap::new(),
+                edge_patterns: Default::default(),
                 image_height: 0,
                 image_width: 0,
                 pattern_height: 0,
                 pattern_width: 0,
-                patterns: Vec::new(),
+                patterns: vec![
+                    Pattern {
+                        pixels: Vec::new(),
+                        weight: 1,
+                    };
+                    10
+                ],
             },
-            pattern_pixels: vec![
-                PatternPixel {
-                    color: 0,
-                    y: 0,
-                    x: 0,
-                    weight: 0,
-                    colors: Vec::new(),
-                    relationships: Vec::new(),
-                };
-                10
+            compatibilities: Vec::new(),
+            supports: Vec::new(),
+            total_weight: 10,
+        };
+
+        let wave = initialize_wave(&propagator, 7, 7, true, &[], &mut Rng::with_seed(7)).unwrap();
+
+        assert_eq!(wave.len(), 49);
+        assert!(wave.contains(0, 3));
+        assert!(wave.contains(4, 3));
+        assert!(wave.contains(6, 3));
+        assert!(wave.contains(8, 3));
+        assert!(wave.contains(48, 3));
+
+        assert!(wave.contains(0, 7));
+        assert!(wave.contains(4, 7));
+        assert!(wave.contains(6, 7));
+        assert!(wave.contains(8, 7));
+        assert!(wave.contains(48, 7));
+
+        assert_eq!(wave.counts[0], 10);
+        assert_eq!(wave.counts[48], 10);
+    }
+
+    #[test]
+    fn it_initializes_a_non_periodic_wave_with_one_entry_per_pattern_position() {
+        let image = Image {
+            width: 4,
+            height: 3,
+            #[rustfmt::skip]
+            data: vec![
+                0, 1, 0, 1,
+                2, 3, 2, 3,
+                4, 5, 4, 5,
             ],
-            total_weight: 1,
         };
+        let propagator = build_propagator(
+            extract_patterns(&[Sample::new(image)], 3, 2, Symmetry::None, true).unwrap(),
+        );
+        let pattern_count = propagator.pattern_data.patterns.len();
+
+        let wave = initialize_wave(&propagator, 7, 5, false, &[], &mut Rng::with_seed(7)).unwrap();
 
-        let wave = initialize_wave(&propagator, 7, 7);
+        // the last 2 columns and the last row of the image are covered by the edge patterns
+        assert_eq!((wave.width, wave.height), (5, 4));
+        assert_eq!(wave.len(), 20);
+        assert!(!wave.periodic);
+        assert_eq!(wave.get_neighbor(4, 4), None);
+
+        // every pattern of a periodic sample is supported in all directions
+        for index in 0..wave.len() {
+            assert_eq!(wave.counts[index], pattern_count);
+        }
+    }
+
+    #[test]
+    fn it_removes_the_patterns_without_support_from_a_neighbor() {
+        let image = Image {
+            width: 5,
+            height: 1,
+            data: vec![0, 1, 0, 1, 2],
+        };
+        // the pattern `12` at the right end of the sample has no neighbor to its right
+        let propagator = build_propagator(
+            extract_patterns(&[Sample::new(image)], 2, 1, Symmetry::None, false).unwrap(),
+        );
+        assert_eq!(propagator.pattern_data.patterns[2].pixels, vec![1, 2]);
+
+        let wave = initialize_wave(&propagator, 6, 1, false, &[], &mut Rng::new()).unwrap();
+
+        // only the last entry of a non-periodic wave has no neighbor to its right
+        assert_eq!(wave.len(), 5);
+        for index in 0..4 {
+            assert!(!wave.contains(index, 2));
+            assert_eq!(wave.counts[index], 2);
+        }
+        assert!(wave.contains(4, 2));
+
+        let wave = initialize_wave(&propagator, 6, 1, true, &[], &mut Rng::new()).unwrap();
+        assert!((0..wave.len()).all(|index| !wave.contains(index, 2)));
+    }
+
+    #[test]
+    fn it_anchors_the_edges_of_the_wave() {
+        let image = Image {
+            width: 4,
+            height: 3,
+            #[rustfmt::skip]
+            data: vec![
+                0, 1, 0, 1,
+                2, 3, 2, 3,
+                4, 5, 4, 5,
+            ],
+        };
+        let propagator = build_propagator(
+            extract_patterns(&[Sample::new(image)], 2, 2, Symmetry::None, true).unwrap(),
+        );
+        let bottom_patterns = propagator.pattern_data.get_edge_patterns(Edge::Bottom);
+
+        let wave =
+            initialize_wave(&propagator, 5, 4, false, &[Edge::Bottom], &mut Rng::new()).unwrap();
+
+        assert_eq!((wave.width, wave.height), (4, 3));
+        for x in 0..4 {
+            let bottom_index = 2 * 4 + x;
+            for pattern_index in wave.pattern_indices(bottom_index) {
+                assert!(bottom_patterns.contains(&pattern_index));
+            }
+
+            // the entries above have to continue the bottom patterns
+            assert!(wave.counts[4 + x] < propagator.pattern_data.patterns.len());
+        }
+    }
+
+    #[test]
+    fn it_anchors_the_edges_of_the_wave_to_the_sample_edges_of_rotated_variants() {
+        let image = Image {
+            width: 3,
+            height: 3,
+            #[rustfmt::skip]
+            data: vec![
+                0, 0, 0,
+                1, 2, 1,
+                3, 3, 3,
+            ],
+        };
+        let propagator = build_propagator(
+            extract_patterns(&[Sample::new(image)], 1, 1, Symmetry::Dihedral, false).unwrap(),
+        );
 
-        assert_eq!(wave.indices[0][3], 3);
-        assert_eq!(wave.indices[4][3], 3);
-        assert_eq!(wave.indices[6][3], 3);
-        assert_eq!(wave.indices[8][3], 3);
-        assert_eq!(wave.indices[48][3], 3);
+        let wave =
+            initialize_wave(&propagator, 4, 4, false, &[Edge::Bottom], &mut Rng::new()).unwrap();
 
-        assert_eq!(wave.indices[0][7], 7);
-        assert_eq!(wave.indices[4][7], 7);
-        assert_eq!(wave.indices[6][7], 7);
-        assert_eq!(wave.indices[8][7], 7);
-        assert_eq!(wave.indices[48][7], 7);
+        // only the bottom row of the sample may end up at the bottom of the wave
+        for x in 0..4 {
+            let bottom_index = 3 * 4 + x;
+            let pixels: Vec<&[u8]> = wave
+                .pattern_indices(bottom_index)
+                .map(|i| propagator.pattern_data.patterns[i].pixels.as_slice())
+                .collect();
+            assert_eq!(pixels, vec![&[3][..]]);
+        }
     }
 }
@@ -2,11 +2,13 @@ use std::path::Path;
 
 use image::GenericImageView;
 
+use crate::error::Error;
+use crate::error::Result;
 use crate::model::image::Image;
 use crate::model::image::RawImage;
 
-pub fn load_image<T: AsRef<Path>>(path: T) -> Image {
-    let (width, height, raw32bit) = load_image_raw(path);
+pub fn load_image<T: AsRef<Path>>(path: T) -> Result<Image> {
+    let (width, height, raw32bit) = load_image_raw(path)?;
     let mut data = vec![0; (width * height) as usize];
 
     for y in 0..height {
@@ -21,16 +23,33 @@ pub fn load_image<T: AsRef<Path>>(path: T) -> Image {
         }
     }
 
-    Image {
+    Ok(Image {
         width,
         height,
         data,
-    }
+    })
 }
 
-pub fn load_image_raw<T: AsRef<Path>>(path: T) -> RawImage {
-    let img = image::open(path).unwrap();
+pub fn load_image_raw<T: AsRef<Path>>(path: T) -> Result<RawImage> {
+    let img = image::open(path).map_err(Error::from_decoding)?;
     let (width, height) = img.dimensions();
-    let raw = img.into_bytes();
-    (width, height, raw)
+    let raw = img.into_rgba8().into_raw();
+    Ok((width, height, raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_loads_an_image() {
+        let image = load_image("./data/flowers.png").unwrap();
+        assert_eq!(image.data.len(), (image.width * image.height) as usize);
+    }
+
+    #[test]
+    fn it_fails_to_load_a_missing_image() {
+        let result = load_image("./data/does_not_exist.png");
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
 }
@@ -0,0 +1,63 @@
+use log::info;
+
+use crate::model::image::Image;
+use crate::model::sample::Sample;
+
+/// Mask value marking a pixel, see `Sample::is_masked`.
+const MASKED: u32 = 0xffff_ffff;
+
+/// Marks the fully transparent pixels of each sample in its mask, so that no pattern
+/// contains them. Samples without a mask get one.
+pub fn mask_transparent_pixels(samples: &mut [Sample]) {
+    let mut masked_pixels = 0;
+
+    for sample in samples.iter_mut() {
+        let Image { width, height, .. } = sample.image;
+        let mask = sample.mask.get_or_insert_with(|| Image {
+            width,
+            height,
+            data: vec![0; (width * height) as usize],
+        });
+
+        for (pixel, masked) in sample.image.data.iter().zip(mask.data.iter_mut()) {
+            if pixel >> 24 == 0 {
+                *masked = MASKED;
+                masked_pixels += 1;
+            }
+        }
+    }
+
+    info!("masked {} transparent sample pixels", masked_pixels);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_masks_transparent_pixels() {
+        let image = Image {
+            width: 3,
+            height: 1,
+            data: vec![0xff00_0000, 0x00ff_ffff, 0x80ff_ffff],
+        };
+        let mask = Image {
+            width: 3,
+            height: 1,
+            data: vec![0xffff_ffff, 0, 0],
+        };
+        let mut samples = [
+            Sample::new(image.clone()),
+            Sample::new(image).with_mask(mask),
+        ];
+
+        mask_transparent_pixels(&mut samples);
+
+        let masked: Vec<Vec<bool>> = samples
+            .iter()
+            .map(|sample| (0..3).map(|i| sample.is_masked(i)).collect())
+            .collect();
+        assert_eq!(masked[0], vec![false, true, false]);
+        assert_eq!(masked[1], vec![true, true, false]);
+    }
+}
@@ -1,176 +1,224 @@
 use crate::model::pattern_propagator::PatternPropagator;
+use crate::model::pattern_propagator::DIRECTIONS;
+use crate::model::trail::Trail;
+use crate::model::trail::TrailEntry;
+use crate::model::wave::Contradiction;
 use crate::model::wave::Wave;
 
-pub fn propagate(wave: &mut Wave, propagator: &PatternPropagator) {
-    // TODO: implications for relationship array???
-    //let mut index_stack = vec![(wave.last_index_collapsed, wave.last_index_collapsed)];
-    let mut index_stack = Vec::new();
-    let width = wave.width as usize;
-    let height = wave.height as usize;
-
-    put_wave_neighbors_on_stack(wave.last_index_collapsed, width, height, &mut index_stack);
-
-    while let Some((i, j)) = index_stack.pop() {
-        let ix = (i % width) as i32;
-        let iy = (i / width) as i32;
-        let jx = (j % width) as i32;
-        let jy = (j / width) as i32;
-        let relx = jx - ix;
-        let rely = jy - iy;
-
-        if wave.indices[j].len() == 0 {
-            for ii in 0..wave.indices.len() {
-                //println!("{} {}", ii, wave.indices[ii].len());
-            }
+/// Removes all patterns which lost the support of a neighbor, starting with the
+/// patterns queued in `wave.unsupported`.
+/// Fails with a `Contradiction` as soon as a wave entry has no patterns left.
+/// Every removal is recorded on the `trail`.
+pub fn propagate(
+    wave: &mut Wave,
+    propagator: &PatternPropagator,
+    trail: &mut Trail,
+) -> Result<(), Contradiction> {
+    while let Some((index, pattern_index)) = wave.unsupported.pop() {
+        if !ban(wave, propagator, trail, index, pattern_index) {
+            continue;
+        }
 
-            for y in 0..10 {
-                for x in 0..10 {
-                    let ii = 10 * y + x;
-                    let len = wave.indices[ii].len();
+        if wave.counts[index] == 0 {
+            wave.unsupported.clear();
+            return Err(Contradiction { index });
+        }
+    }
 
-                    if len < 1000 {
-                        print!("{:3} ", len);
-                    } else {
-                        print!("xxx ");
-                    }
-                }
-                println!();
-            }
+    Ok(())
+}
 
-            todo!("implement contradiction handling");
-        }
+/// Removes the pattern from the wave entry and withdraws its support from the neighbors.
+/// Neighbor patterns which lose their last support are queued in `wave.unsupported`.
+/// Returns `false` if the pattern was already removed.
+pub fn ban(
+    wave: &mut Wave,
+    propagator: &PatternPropagator,
+    trail: &mut Trail,
+    index: usize,
+    pattern_index: usize,
+) -> bool {
+    if !wave.remove(index, pattern_index) {
+        return false;
+    }
 
-        let mut remove_occured = false;
-
-        //println!(
-        //    "popped: (({}, {}), ({}, {})), remaining: {:?}",
-        //    ix, iy, jx, jy, index_stack
-        //);
-
-        let mut keeps = vec![false; wave.indices[j].len()];
-
-        for k in 0..wave.indices[i].len() {
-            let u = wave.indices[i][k];
-            for l in 0..wave.indices[j].len() {
-                let v = wave.indices[j][l];
-                if let Some(r) = calculate_relationship_index(propagator, relx, rely, u, v) {
-                    print!("r");
-                    if propagator.pattern_pixels[u].relationships[r] {
-                        keeps[l] = true;
-                        print!("k");
-                    }
-                }
+    trail.push(TrailEntry::Removal {
+        index,
+        pattern_index,
+    });
+
+    for direction in 0..DIRECTIONS.len() {
+        let Some(neighbor) = wave.get_neighbor(index, direction) else {
+            continue;
+        };
+        let Some(compatible) = propagator.get_neighbor_patterns(pattern_index, direction) else {
+            continue;
+        };
+
+        let opposite = DIRECTIONS.len() - 1 - direction;
+        for &v in compatible {
+            let si = wave.get_support_index(neighbor, v, opposite);
+            wave.supports[si] -= 1;
+
+            if wave.supports[si] == 0 && wave.contains(neighbor, v) {
+                wave.unsupported.push((neighbor, v));
             }
         }
+    }
 
-        for k in 0..keeps.len() {
-            let l = keeps.len() - 1 - k;
-            if !keeps[l] {
-                wave.indices[j].swap_remove(l);
-                remove_occured = true;
-                //print!("{}|", wave.indices[j].len());
-            }
-        }
-        println!();
+    true
+}
 
-        if remove_occured {
-            put_wave_neighbors_on_stack(j, width, height, &mut index_stack);
+/// Inserts a banned pattern back into the wave entry and restores its support of the neighbors.
+/// Patterns have to be unbanned in the reverse order they were banned.
+pub fn unban(wave: &mut Wave, propagator: &PatternPropagator, index: usize, pattern_index: usize) {
+    if !wave.insert(index, pattern_index) {
+        return;
+    }
+
+    for direction in 0..DIRECTIONS.len() {
+        let Some(neighbor) = wave.get_neighbor(index, direction) else {
+            continue;
+        };
+        let Some(compatible) = propagator.get_neighbor_patterns(pattern_index, direction) else {
+            continue;
+        };
+
+        let opposite = DIRECTIONS.len() - 1 - direction;
+        for &v in compatible {
+            let si = wave.get_support_index(neighbor, v, opposite);
+            wave.supports[si] += 1;
         }
     }
 }
 
-/// Calculates the index inside the relationships vector of a pattern_pixel.
-/// `relx` and `rely` are the relative coordinates inside the wave of the
-/// `second_pixel_index` as seen from the `first_pixel_index`.
-/// E.g. the first pixel is at (5, 5), the second at (7, 4), then
-/// `relx = 2`, `rely = -1`
-fn calculate_relationship_index(
-    propagator: &PatternPropagator,
-    relx: i32,
-    rely: i32,
-    first_pixel_index: usize,
-    second_pixel_index: usize,
-) -> Option<usize> {
-    let first_pixel = &propagator.pattern_pixels[first_pixel_index];
-    let x = first_pixel.x as i32 + relx;
-    let y = first_pixel.y as i32 + rely;
-    let w = propagator.pattern_data.pattern_width as i32;
-    let h = propagator.pattern_data.pattern_height as i32;
-
-    // TODO: build_propagator is wrong
-    print!("{:?}", (x, y, relx, rely, first_pixel_index, second_pixel_index));
-
-    if x < 0 || x >= w || y < 0 || y >= h {
-        return None;
+#[cfg(test)]
+mod tests {
+    use crate::controller::build_propagator::build_propagator;
+    use crate::controller::extract_patterns::extract_patterns;
+    use crate::model::image::Image;
+    use crate::model::sample::Sample;
+    use crate::model::symmetry::Symmetry;
+
+    use super::*;
+
+    fn build_simple_propagator() -> PatternPropagator {
+        let image = Image {
+            width: 4,
+            height: 3,
+            #[rustfmt::skip]
+            data: vec![
+                0, 1, 2, 3,
+                4, 5, 6, 7,
+                8, 9, 10, 11,
+            ],
+        };
+
+        build_propagator(
+            extract_patterns(&[Sample::new(image)], 3, 2, Symmetry::None, true).unwrap(),
+        )
     }
 
-    let s1 = w as usize;
-    let s2 = (w * h) as usize;
-    Some(second_pixel_index * s2 + y as usize * s1 + x as usize)
-}
+    /// Creates a 2x1 wave by banning all patterns which are not listed in `indices`,
+    /// the bans are not propagated yet.
+    fn create_wave(propagator: &PatternPropagator, indices: &[Vec<usize>]) -> Wave {
+        let mut wave = create_full_wave(propagator);
+
+        for (index, pattern_indices) in indices.iter().enumerate() {
+            for pattern_index in 0..propagator.pattern_data.patterns.len() {
+                if !pattern_indices.contains(&pattern_index) {
+                    ban(
+                        &mut wave,
+                        propagator,
+                        &mut Trail::new(false),
+                        index,
+                        pattern_index,
+                    );
+                }
+            }
+        }
 
-fn put_wave_neighbors_on_stack(
-    index: usize,
-    width: usize,
-    height: usize,
-    index_stack: &mut Vec<(usize, usize)>,
-) {
-    if index >= width * height {
-        panic!("index overflow");
+        wave
     }
 
-    let row = index / width;
-    let col = index % width;
+    fn create_full_wave(propagator: &PatternPropagator) -> Wave {
+        let mut wave = Wave::new(2, 1, propagator.get_weights());
+        wave.supports = propagator.supports.repeat(wave.len());
+        wave
+    }
 
-    let has_top_row = row > 0;
-    let has_bottom_row = row < height - 1;
-    let has_left_col = col > 0;
-    let has_right_col = col < width - 1;
+    #[test]
+    fn it_removes_incompatible_patterns() {
+        let propagator = build_simple_propagator();
+        let u = 5;
+        let v1 = 5;
+        let v2 = 6;
+
+        let mut wave = create_wave(&propagator, &[vec![u], vec![v1, v2]]);
+
+        let mut trail = Trail::new(true);
+
+        assert_eq!(propagate(&mut wave, &propagator, &mut trail), Ok(()));
+        assert_eq!(wave.pattern_indices(0).collect::<Vec<_>>(), vec![u]);
+        assert_eq!(wave.pattern_indices(1).collect::<Vec<_>>(), vec![v2]);
+        assert_eq!(
+            trail.entries,
+            vec![TrailEntry::Removal {
+                index: 1,
+                pattern_index: v1
+            }]
+        );
+    }
 
-    // Top neighbor
-    if has_top_row {
-        index_stack.push((index, index - width));
+    #[test]
+    fn it_reports_a_contradiction() {
+        let propagator = build_simple_propagator();
+        let u = 5;
+        let v = 5;
 
-        // Top-left neighbor
-        if has_left_col {
-            index_stack.push((index, index - width - 1));
-        }
+        let mut wave = create_wave(&propagator, &[vec![u], vec![v]]);
 
-        // Top-right neighbor
-        if has_right_col {
-            index_stack.push((index, index - width + 1));
-        }
+        // u and v do not support each other, so either entry may run out of patterns first
+        let result = propagate(&mut wave, &propagator, &mut Trail::new(false));
+        assert!(matches!(result, Err(Contradiction { index: 0 | 1 })));
+        assert!(wave.unsupported.is_empty());
     }
 
-    // Bottom neighbor
-    if has_bottom_row {
-        index_stack.push((index, index + width));
+    #[test]
+    fn it_withdraws_the_support_of_banned_patterns() {
+        let propagator = build_simple_propagator();
+        let mut wave = create_full_wave(&propagator);
+        let left = 3;
+        let right = 4;
 
-        // Bottom-left neighbor
-        if has_left_col {
-            index_stack.push((index, index + width - 1));
-        }
+        // the patterns compatible with 5 to its right are exactly 6
+        assert_eq!(propagator.get_neighbor_patterns(5, right), Some(&[6][..]));
+        assert_eq!(wave.supports[wave.get_support_index(1, 6, left)], 1);
 
-        // Bottom-right neighbor
-        if has_right_col {
-            index_stack.push((index, index + width + 1));
-        }
-    }
+        assert!(ban(&mut wave, &propagator, &mut Trail::new(false), 0, 5));
+        assert!(!ban(&mut wave, &propagator, &mut Trail::new(false), 0, 5));
 
-    // Left neighbor
-    if has_left_col {
-        index_stack.push((index, index - 1));
+        assert_eq!(wave.supports[wave.get_support_index(1, 6, left)], 0);
+        assert_eq!(wave.unsupported, vec![(1, 6)]);
     }
 
-    // Right neighbor
-    if has_right_col {
-        index_stack.push((index, index + 1));
+    #[test]
+    fn it_restores_the_support_of_unbanned_patterns() {
+        let propagator = build_simple_propagator();
+        let mut wave = create_full_wave(&propagator);
+        let supports = wave.supports.clone();
+
+        let mut trail = Trail::new(false);
+        ban(&mut wave, &propagator, &mut trail, 0, 5);
+        ban(&mut wave, &propagator, &mut trail, 1, 2);
+        ban(&mut wave, &propagator, &mut trail, 0, 3);
+        unban(&mut wave, &propagator, 0, 3);
+        unban(&mut wave, &propagator, 1, 2);
+        unban(&mut wave, &propagator, 0, 5);
+
+        assert_eq!(wave.supports, supports);
+        assert_eq!(wave.counts, vec![12, 12]);
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
     #[test]
     fn it_yyy() {
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::time::Instant;
+
+use log::info;
+
+use crate::model::sample::Sample;
+
+/// Reduces the colors of all samples to a shared palette of at most `palette_size` colors
+/// with the median cut algorithm. Logs how many unique windows of the pattern size this saves.
+pub fn quantize_samples(
+    samples: &mut [Sample],
+    palette_size: usize,
+    pattern_width: u32,
+    pattern_height: u32,
+    periodic: bool,
+) {
+    info!("quantizing samples...");
+    let now = Instant::now();
+
+    let color_counts = count_colors(samples);
+    let windows_before = count_unique_windows(samples, pattern_width, pattern_height, periodic);
+
+    let mapping = median_cut(color_counts.clone(), palette_size);
+    for sample in samples.iter_mut() {
+        for color in sample.image.data.iter_mut() {
+            *color = mapping[color];
+        }
+    }
+
+    let colors_after = mapping.values().collect::<HashSet<_>>().len();
+    let windows_after = count_unique_windows(samples, pattern_width, pattern_height, periodic);
+
+    info!("  done, took {} ms", now.elapsed().as_millis());
+    info!(
+        "  number of colors: {} -> {}",
+        color_counts.len(),
+        colors_after
+    );
+    info!(
+        "  number of unique patterns: {} -> {}, saved {}",
+        windows_before,
+        windows_after,
+        windows_before - windows_after
+    );
+}
+
+/// Counts how often each color occurs, sorted by color to keep the quantization deterministic.
+fn count_colors(samples: &[Sample]) -> Vec<(u32, u32)> {
+    let mut counts: HashMap<u32, u32> = HashMap::new();
+    for sample in samples {
+        for &color in &sample.image.data {
+            *counts.entry(color).or_insert(0) += 1;
+        }
+    }
+
+    let mut counts: Vec<(u32, u32)> = counts.into_iter().collect();
+    counts.sort_unstable();
+    counts
+}
+
+/// Counts the unique windows of the samples in their original orientation,
+/// skipping windows with masked pixels.
+fn count_unique_windows(
+    samples: &[Sample],
+    pattern_width: u32,
+    pattern_height: u32,
+    periodic: bool,
+) -> usize {
+    let mut windows: HashSet<Vec<u32>> = HashSet::new();
+
+    for sample in samples {
+        let image = &sample.image;
+        let (window_columns, window_rows) = if periodic {
+            (image.width, image.height)
+        } else {
+            (
+                (image.width + 1).saturating_sub(pattern_width),
+                (image.height + 1).saturating_sub(pattern_height),
+            )
+        };
+
+        for image_y in 0..window_rows {
+            for image_x in 0..window_columns {
+                let mut window = Vec::with_capacity((pattern_width * pattern_height) as usize);
+                let mut is_masked = false;
+                for pattern_y in 0..pattern_height {
+                    for pattern_x in 0..pattern_width {
+                        let scan_x = (image_x + pattern_x) % image.width;
+                        let scan_y = (image_y + pattern_y) % image.height;
+                        let image_index = (image.width * scan_y + scan_x) as usize;
+                        window.push(image.data[image_index]);
+                        is_masked |= sample.is_masked(image_index);
+                    }
+                }
+
+                if !is_masked {
+                    windows.insert(window);
+                }
+            }
+        }
+    }
+
+    windows.len()
+}
+
+/// Splits the colors into at most `palette_size` boxes, each time halving the box with the
+/// widest channel range at the weighted median of that channel.
+/// Returns for each color the weighted average color of its box.
+fn median_cut(color_counts: Vec<(u32, u32)>, palette_size: usize) -> HashMap<u32, u32> {
+    let mut boxes = vec![color_counts];
+
+    while boxes.len() < palette_size {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(i, colors)| (i, get_widest_channel(colors)))
+            .max_by_key(|&(_, (_, range))| range);
+
+        let Some((i, (channel, _))) = widest else {
+            break;
+        };
+
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_by_key(|&(color, _)| (get_channel(color, channel), color));
+
+        let half: u32 = colors.iter().map(|&(_, count)| count).sum::<u32>() / 2;
+        let mut cumulative = 0;
+        let mut split = 1;
+        for (j, &(_, count)) in colors.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= half {
+                split = (j + 1).clamp(1, colors.len() - 1);
+                break;
+            }
+        }
+
+        let upper = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    let mut mapping = HashMap::new();
+    for colors in &boxes {
+        let average = get_average_color(colors);
+        for &(color, _) in colors {
+            mapping.insert(color, average);
+        }
+    }
+
+    mapping
+}
+
+fn get_channel(color: u32, channel: usize) -> u8 {
+    color.to_le_bytes()[channel]
+}
+
+/// Returns the RGBA channel with the largest difference between its values and that difference.
+fn get_widest_channel(colors: &[(u32, u32)]) -> (usize, u8) {
+    (0..4)
+        .map(|channel| {
+            let values = colors.iter().map(|&(color, _)| get_channel(color, channel));
+            let min = values.clone().min().unwrap_or(0);
+            let max = values.max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|&(channel, range)| (range, std::cmp::Reverse(channel)))
+        .unwrap()
+}
+
+fn get_average_color(colors: &[(u32, u32)]) -> u32 {
+    let total: u64 = colors.iter().map(|&(_, count)| count as u64).sum();
+    let mut channels = [0; 4];
+
+    for (channel, value) in channels.iter_mut().enumerate() {
+        let sum: u64 = colors
+            .iter()
+            .map(|&(color, count)| get_channel(color, channel) as u64 * count as u64)
+            .sum();
+        *value = ((sum + total / 2) / total) as u8;
+    }
+
+    u32::from_le_bytes(channels)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::image::Image;
+
+    use super::*;
+
+    fn gray(value: u8) -> u32 {
+        u32::from_le_bytes([value, value, value, 255])
+    }
+
+    #[test]
+    fn it_reduces_the_number_of_colors() {
+        let image = Image {
+            width: 16,
+            height: 1,
+            data: (0..16).map(|i| gray(i * 16)).collect(),
+        };
+        let mut samples = [Sample::new(image)];
+
+        quantize_samples(&mut samples, 4, 2, 1, true);
+
+        let colors: Vec<u32> = samples[0].image.data.clone();
+        let unique: HashSet<u32> = colors.iter().copied().collect();
+        assert_eq!(unique.len(), 4);
+
+        // neighboring grays end up in the same box
+        assert_eq!(colors[0], colors[1]);
+        assert_eq!(colors[14], colors[15]);
+        assert_ne!(colors[0], colors[15]);
+    }
+
+    #[test]
+    fn it_keeps_the_colors_if_the_palette_is_large_enough() {
+        let image = Image {
+            width: 3,
+            height: 1,
+            data: vec![gray(0), gray(100), gray(0)],
+        };
+        let mut samples = [Sample::new(image.clone())];
+
+        quantize_samples(&mut samples, 2, 1, 1, true);
+
+        assert_eq!(samples[0].image.data, image.data);
+    }
+
+    #[test]
+    fn it_counts_unique_windows() {
+        let image = Image {
+            width: 4,
+            height: 1,
+            data: vec![0, 1, 0, 1],
+        };
+        let samples = [Sample::new(image)];
+
+        assert_eq!(count_unique_windows(&samples, 2, 1, true), 2);
+        assert_eq!(count_unique_windows(&samples, 3, 1, false), 2);
+        assert_eq!(count_unique_windows(&samples, 4, 1, true), 2);
+    }
+}
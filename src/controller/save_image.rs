@@ -0,0 +1,12 @@
+use std::path::Path;
+
+use image::ColorType;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::model::image::RawImage;
+
+pub fn save_image_raw<T: AsRef<Path>>(path: T, image: &RawImage) -> Result<()> {
+    let (width, height, raw) = image;
+    image::save_buffer(path, raw, *width, *height, ColorType::Rgba8).map_err(Error::from_encoding)
+}
@@ -0,0 +1,432 @@
+use fastrand::Rng;
+
+use crate::model::cell_selection::CellSelection;
+use crate::model::wave::Wave;
+
+/// Chooses the wave entry which is observed next.
+pub trait CellSelector {
+    /// Returns the index of an uncollapsed wave entry, or `None` if all entries are collapsed.
+    fn select_cell(&mut self, wave: &mut Wave, rng: &mut Rng) -> Option<usize>;
+}
+
+pub fn create_cell_selector(selection: CellSelection) -> Box<dyn CellSelector> {
+    match selection {
+        CellSelection::MinEntropy => Box::new(MinEntropySelector),
+        CellSelection::Neighborhood => Box::new(NeighborhoodSelector),
+        CellSelection::Scanline => Box::new(ScanlineSelector),
+        CellSelection::RemainingValues => Box::new(RemainingValuesSelector),
+        CellSelection::Random => Box::new(RandomSelector),
+    }
+}
+
+pub struct MinEntropySelector;
+
+impl CellSelector for MinEntropySelector {
+    fn select_cell(&mut self, wave: &mut Wave, _rng: &mut Rng) -> Option<usize> {
+        wave.find_lowest_entropy()
+    }
+}
+
+/// Searches the 8-neighborhood of the last collapsed entry first, which lets the collapsed
+/// area grow like a crystal instead of starting at many places which might not fit together.
+pub struct NeighborhoodSelector;
+
+impl CellSelector for NeighborhoodSelector {
+    fn select_cell(&mut self, wave: &mut Wave, _rng: &mut Rng) -> Option<usize> {
+        find_lowest_entropy_index(wave, get_last_collapsed_neighborhood(wave))
+            .or_else(|| wave.find_lowest_entropy())
+    }
+}
+
+pub struct ScanlineSelector;
+
+impl CellSelector for ScanlineSelector {
+    fn select_cell(&mut self, wave: &mut Wave, _rng: &mut Rng) -> Option<usize> {
+        (0..wave.len()).find(|&i| wave.counts[i] > 1)
+    }
+}
+
+/// Picks the first entry with the minimum number of remaining values.
+pub struct RemainingValuesSelector;
+
+impl CellSelector for RemainingValuesSelector {
+    fn select_cell(&mut self, wave: &mut Wave, _rng: &mut Rng) -> Option<usize> {
+        (0..wave.len())
+            .filter(|&i| wave.counts[i] > 1)
+            .min_by_key(|&i| wave.counts[i])
+    }
+}
+
+pub struct RandomSelector;
+
+impl CellSelector for RandomSelector {
+    fn select_cell(&mut self, wave: &mut Wave, rng: &mut Rng) -> Option<usize> {
+        let uncollapsed: Vec<usize> = (0..wave.len()).filter(|&i| wave.counts[i] > 1).collect();
+
+        if uncollapsed.is_empty() {
+            return None;
+        }
+
+        Some(uncollapsed[rng.usize(..uncollapsed.len())])
+    }
+}
+
+/// Returns the first of the uncollapsed `indices` with the lowest entropy,
+/// including the tie breaking noise of the wave.
+fn find_lowest_entropy_index(
+    wave: &Wave,
+    indices: impl IntoIterator<Item = usize>,
+) -> Option<usize> {
+    let mut lowest_entropy_index = None;
+    let mut lowest_entropy_value = f64::MAX;
+
+    for i in indices {
+        if wave.counts[i] <= 1 {
+            continue;
+        }
+
+        let entropy = wave.get_noisy_entropy(i);
+
+        if entropy < lowest_entropy_value {
+            lowest_entropy_index = Some(i);
+            lowest_entropy_value = entropy;
+        }
+    }
+
+    lowest_entropy_index
+}
+
+fn get_last_collapsed_neighborhood(wave: &Wave) -> Vec<usize> {
+    calculate_adjacent_indices(
+        wave.width as usize,
+        wave.height as usize,
+        wave.periodic,
+        wave.last_index_collapsed,
+    )
+    .unwrap_or_default()
+}
+
+/// Returns the indices of the 8-neighborhood of `index`, or `None` if `index` lies
+/// outside of the wave.
+fn calculate_adjacent_indices(
+    width: usize,
+    height: usize,
+    periodic: bool,
+    index: usize,
+) -> Option<Vec<usize>> {
+    if index >= width * height {
+        return None;
+    }
+
+    let mut adjacent_indices = Vec::with_capacity(8);
+
+    let row = index / width;
+    let col = index % width;
+
+    if periodic {
+        let top_row = (row + height - 1) % height;
+        let bottom_row = (row + 1) % height;
+        let left_col = (col + width - 1) % width;
+        let right_col = (col + 1) % width;
+
+        for r in [top_row, row, bottom_row] {
+            for c in [left_col, col, right_col] {
+                if r != row || c != col {
+                    adjacent_indices.push(r * width + c);
+                }
+            }
+        }
+
+        return Some(adjacent_indices);
+    }
+
+    let has_top_row = row > 0;
+    let has_bottom_row = row < height - 1;
+    let has_left_col = col > 0;
+    let has_right_col = col < width - 1;
+
+    // Top neighbor
+    if has_top_row {
+        adjacent_indices.push(index - width);
+
+        // Top-left neighbor
+        if has_left_col {
+            adjacent_indices.push(index - width - 1);
+        }
+
+        // Top-right neighbor
+        if has_right_col {
+            adjacent_indices.push(index - width + 1);
+        }
+    }
+
+    // Bottom neighbor
+    if has_bottom_row {
+        adjacent_indices.push(index + width);
+
+        // Bottom-left neighbor
+        if has_left_col {
+            adjacent_indices.push(index + width - 1);
+        }
+
+        // Bottom-right neighbor
+        if has_right_col {
+            adjacent_indices.push(index + width + 1);
+        }
+    }
+
+    // Left neighbor
+    if has_left_col {
+        adjacent_indices.push(index - 1);
+    }
+
+    // Right neighbor
+    if has_right_col {
+        adjacent_indices.push(index + 1);
+    }
+
+    Some(adjacent_indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use clap::ValueEnum;
+
+    use crate::model::color_map::ColorMap;
+    use crate::model::pattern_data::Pattern;
+    use crate::model::pattern_data::PatternData;
+    use crate::model::pattern_propagator::PatternPropagator;
+
+    use super::*;
+
+    #[test]
+    fn it_calculates_adjacent_indices() {
+        // Example, width = 5, height = 4
+        //  0  1  2  3  4
+        //  5  6  7  8  9
+        // 10 11 12 13 14
+        // 15 16 17 18 19
+        let width = 5;
+        let height = 4;
+
+        // corners
+        let indices = set(calculate_adjacent_indices(width, height, false, 0).unwrap());
+        assert_eq!(indices, set(vec![1, 5, 6]));
+        let indices = set(calculate_adjacent_indices(width, height, false, 4).unwrap());
+        assert_eq!(indices, set(vec![3, 8, 9]));
+        let indices = set(calculate_adjacent_indices(width, height, false, 15).unwrap());
+        assert_eq!(indices, set(vec![10, 11, 16]));
+        let indices = set(calculate_adjacent_indices(width, height, false, 19).unwrap());
+        assert_eq!(indices, set(vec![13, 14, 18]));
+
+        // edges
+        let indices = set(calculate_adjacent_indices(width, height, false, 1).unwrap());
+        assert_eq!(indices, set(vec![0, 2, 5, 6, 7]));
+        let indices = set(calculate_adjacent_indices(width, height, false, 14).unwrap());
+        assert_eq!(indices, set(vec![8, 9, 13, 18, 19]));
+        let indices = set(calculate_adjacent_indices(width, height, false, 17).unwrap());
+        assert_eq!(indices, set(vec![11, 12, 13, 16, 18]));
+        let indices = set(calculate_adjacent_indices(width, height, false, 5).unwrap());
+        assert_eq!(indices, set(vec![0, 1, 6, 10, 11]));
+
+        // center
+        let indices = set(calculate_adjacent_indices(width, height, false, 6).unwrap());
+        assert_eq!(indices, set(vec![0, 1, 2, 5, 7, 10, 11, 12]));
+        let indices = set(calculate_adjacent_indices(width, height, false, 12).unwrap());
+        assert_eq!(indices, set(vec![6, 7, 8, 11, 13, 16, 17, 18]));
+    }
+
+    #[test]
+    fn it_calculates_periodic_adjacent_indices() {
+        // Example, width = 5, height = 4
+        //  0  1  2  3  4
+        //  5  6  7  8  9
+        // 10 11 12 13 14
+        // 15 16 17 18 19
+        let width = 5;
+        let height = 4;
+
+        // corners
+        let indices = set(calculate_adjacent_indices(width, height, true, 0).unwrap());
+        assert_eq!(indices, set(vec![1, 4, 5, 6, 9, 15, 16, 19]));
+        let indices = set(calculate_adjacent_indices(width, height, true, 19).unwrap());
+        assert_eq!(indices, set(vec![0, 3, 4, 10, 13, 14, 15, 18]));
+
+        // edges
+        let indices = set(calculate_adjacent_indices(width, height, true, 14).unwrap());
+        assert_eq!(indices, set(vec![5, 8, 9, 10, 13, 15, 18, 19]));
+
+        // center
+        let indices = set(calculate_adjacent_indices(width, height, true, 6).unwrap());
+        assert_eq!(indices, set(vec![0, 1, 2, 5, 7, 10, 11, 12]));
+    }
+
+    #[test]
+    fn it_rejects_adjacent_indices_of_an_index_outside_of_the_wave() {
+        let width = 5;
+        let height = 4;
+        assert_eq!(calculate_adjacent_indices(width, height, false, 100), None);
+        assert_eq!(calculate_adjacent_indices(width, height, true, 20), None);
+    }
+
+    #[test]
+    fn it_finds_the_lowest_entropy_in_an_8_neighborhood() {
+        let propagator = create_propagator_with_simple_weights();
+
+        // the pattern weights are noted in the comments
+        let mut wave = create_wave(
+            &propagator,
+            &[
+                vec![0],
+                vec![2, 10, 11],        // 2, 2, 2
+                vec![4, 1],             // 4, 1
+                vec![4, 1, 6],          // 4, 1, 1
+                vec![0, 1, 6, 7, 8, 9], // 1, 1, 1, 1, 1, 1
+                vec![0],
+            ],
+        );
+
+        // Note that wave elements in the neighborhood all have a summed weight of 6
+        // but index 3 wins since it is "densest".
+        // Also note that index 2 has lowest (non-singular) global entropy but is not picked
+        // since it is not in the neighborhood.
+        let lowest_entropy_index =
+            NeighborhoodSelector.select_cell(&mut wave, &mut Rng::with_seed(0));
+        assert_eq!(lowest_entropy_index, Some(3));
+
+        let lowest_entropy_index =
+            MinEntropySelector.select_cell(&mut wave, &mut Rng::with_seed(0));
+        assert_eq!(lowest_entropy_index, Some(2));
+    }
+
+    #[test]
+    fn it_extends_the_lowest_entropy_search_to_the_entire_wave() {
+        let propagator = create_propagator_with_simple_weights();
+
+        let mut wave = create_wave(
+            &propagator,
+            &[vec![0], vec![1], vec![4, 1], vec![1], vec![1], vec![3, 2]],
+        );
+
+        let lowest_entropy_index =
+            NeighborhoodSelector.select_cell(&mut wave, &mut Rng::with_seed(0));
+        assert_eq!(lowest_entropy_index, Some(2));
+    }
+
+    #[test]
+    fn it_selects_the_first_uncollapsed_entry_in_scanline_order() {
+        let propagator = create_propagator_with_simple_weights();
+
+        let mut wave = create_wave(
+            &propagator,
+            &[
+                vec![0],
+                vec![1],
+                vec![4, 1, 6],
+                vec![1],
+                vec![4, 1],
+                vec![0],
+            ],
+        );
+
+        let index = ScanlineSelector.select_cell(&mut wave, &mut Rng::with_seed(0));
+        assert_eq!(index, Some(2));
+    }
+
+    #[test]
+    fn it_selects_the_entry_with_the_fewest_remaining_patterns() {
+        let propagator = create_propagator_with_simple_weights();
+
+        // index 5 has as few patterns as index 2 but comes later
+        let mut wave = create_wave(
+            &propagator,
+            &[
+                vec![0],
+                vec![2, 3, 4],
+                vec![4, 5],
+                vec![0, 1, 6, 7],
+                vec![1],
+                vec![4, 3],
+            ],
+        );
+
+        let index = RemainingValuesSelector.select_cell(&mut wave, &mut Rng::with_seed(0));
+        assert_eq!(index, Some(2));
+    }
+
+    #[test]
+    fn it_selects_random_uncollapsed_entries() {
+        let propagator = create_propagator_with_simple_weights();
+        let mut rng = Rng::with_seed(0);
+
+        let mut wave = create_wave(
+            &propagator,
+            &[vec![0], vec![2, 3], vec![4], vec![1], vec![4, 1], vec![0]],
+        );
+
+        let indices: HashSet<usize> = (0..100)
+            .filter_map(|_| RandomSelector.select_cell(&mut wave, &mut rng))
+            .collect();
+        assert_eq!(indices, set(vec![1, 4]));
+    }
+
+    #[test]
+    fn it_selects_nothing_if_the_wave_is_collapsed() {
+        let propagator = create_propagator_with_simple_weights();
+        let mut wave = create_wave(&propagator, &vec![vec![0]; 6]);
+
+        for selection in CellSelection::value_variants() {
+            let mut selector = create_cell_selector(*selection);
+            let index = selector.select_cell(&mut wave, &mut Rng::with_seed(0));
+            assert_eq!(index, None);
+        }
+    }
+
+    fn set(v: Vec<usize>) -> HashSet<usize> {
+        v.into_iter().collect()
+    }
+
+    fn create_pattern_with_weight(weight: u32) -> Pattern {
+        Pattern {
+            pixels: Vec::new(),
+            weight,
+        }
+    }
+
+    fn create_propagator_with_simple_weights() -> PatternPropagator {
+        PatternPropagator {
+            pattern_data: PatternData {
+                color_map: ColorMap::new(),
+                edge_patterns: Default::default(),
+                image_height: 0,
+                image_width: 0,
+                pattern_height: 0,
+                pattern_width: 0,
+                patterns: vec![
+                    create_pattern_with_weight(1),
+                    create_pattern_with_weight(1),
+                    create_pattern_with_weight(2),
+                    create_pattern_with_weight(3),
+                    create_pattern_with_weight(4),
+                    create_pattern_with_weight(5),
+                    create_pattern_with_weight(1),
+                    create_pattern_with_weight(1),
+                    create_pattern_with_weight(1),
+                    create_pattern_with_weight(1),
+                    create_pattern_with_weight(2),
+                    create_pattern_with_weight(2),
+                ],
+            },
+            compatibilities: Vec::new(),
+            supports: Vec::new(),
+            total_weight: 16,
+        }
+    }
+
+    /// Creates a 3x2 wave whose last collapsed entry is the top left one.
+    fn create_wave(propagator: &PatternPropagator, indices: &[Vec<usize>]) -> Wave {
+        Wave::from_indices(3, 2, propagator.get_weights(), indices)
+    }
+}
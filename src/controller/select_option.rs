@@ -0,0 +1,228 @@
+use fastrand::Rng;
+
+use crate::model::option_selection::OptionSelection;
+use crate::model::wave::Wave;
+
+/// Chooses the pattern a wave entry collapses to.
+pub trait OptionSelector {
+    /// Returns one of the patterns left in the wave entry at `index`,
+    /// which must contain at least one pattern.
+    fn select_option(&mut self, wave: &Wave, index: usize, rng: &mut Rng) -> usize;
+}
+
+/// Creates the selector for `selection`, the `temperature` is only used by
+/// `OptionSelection::Temperature`.
+pub fn create_option_selector(
+    selection: OptionSelection,
+    temperature: f64,
+) -> Box<dyn OptionSelector> {
+    match selection {
+        OptionSelection::Weighted => Box::new(WeightedSelector),
+        OptionSelection::Heaviest => Box::new(HeaviestSelector),
+        OptionSelection::Uniform => Box::new(UniformSelector),
+        OptionSelection::Temperature => Box::new(TemperatureSelector { temperature }),
+    }
+}
+
+/// Draws a number below the weight sum of the entry and walks the cumulative weights of its
+/// patterns in ascending pattern order without allocating. The result only depends on the
+/// seed and the set of remaining patterns, not on how the wave stores them.
+pub struct WeightedSelector;
+
+impl OptionSelector for WeightedSelector {
+    fn select_option(&mut self, wave: &Wave, index: usize, rng: &mut Rng) -> usize {
+        let mut k = rng.usize(..wave.weight_sums[index] as usize);
+
+        for pi in wave.pattern_indices(index) {
+            let weight = wave.weights[pi] as usize;
+            if k < weight {
+                return pi;
+            }
+            k -= weight;
+        }
+
+        unreachable!("the weight sum of wave entry {} is out of date", index)
+    }
+}
+
+pub struct HeaviestSelector;
+
+impl OptionSelector for HeaviestSelector {
+    fn select_option(&mut self, wave: &Wave, index: usize, _rng: &mut Rng) -> usize {
+        let mut heaviest_index = 0;
+        let mut heaviest_value = 0;
+        for pi in wave.pattern_indices(index) {
+            let weight = wave.weights[pi];
+
+            if weight > heaviest_value {
+                heaviest_value = weight;
+                heaviest_index = pi;
+            }
+        }
+        heaviest_index
+    }
+}
+
+pub struct UniformSelector;
+
+impl OptionSelector for UniformSelector {
+    fn select_option(&mut self, wave: &Wave, index: usize, rng: &mut Rng) -> usize {
+        let k = rng.usize(..wave.counts[index]);
+        wave.pattern_indices(index).nth(k).unwrap()
+    }
+}
+
+/// Samples with the weights raised to `1 / temperature`, a temperature of 1 equals
+/// `WeightedSelector`, 0 approaches `HeaviestSelector` and infinity `UniformSelector`.
+/// The weights are divided by the largest remaining weight first, so that low temperatures
+/// don't overflow the scaled weights.
+pub struct TemperatureSelector {
+    pub temperature: f64,
+}
+
+impl OptionSelector for TemperatureSelector {
+    fn select_option(&mut self, wave: &Wave, index: usize, rng: &mut Rng) -> usize {
+        let exponent = 1.0 / self.temperature;
+        let max_weight = wave
+            .pattern_indices(index)
+            .map(|pi| wave.weights[pi])
+            .max()
+            .unwrap_or(1) as f64;
+        let scaled_weight = |pi: usize| (wave.weights[pi] as f64 / max_weight).powf(exponent);
+
+        let scaled_weight_sum: f64 = wave.pattern_indices(index).map(scaled_weight).sum();
+        let mut remaining = rng.f64() * scaled_weight_sum;
+
+        let mut chosen_pattern_index = 0;
+        for pi in wave.pattern_indices(index) {
+            chosen_pattern_index = pi;
+            remaining -= scaled_weight(pi);
+            if remaining < 0.0 {
+                break;
+            }
+        }
+        chosen_pattern_index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts how often each pattern of a single entry wave with the weights 1, 2, 7 and 5
+    /// is chosen, the last pattern is removed from the entry.
+    fn count_selections(selector: &mut dyn OptionSelector) -> Vec<usize> {
+        let wave = Wave::from_indices(1, 1, vec![1, 2, 7, 5], &[vec![0, 1, 2]]);
+        let mut rng = Rng::with_seed(0);
+        let mut counts = vec![0; 4];
+
+        for _ in 0..10000 {
+            counts[selector.select_option(&wave, 0, &mut rng)] += 1;
+        }
+
+        counts
+    }
+
+    #[test]
+    fn it_selects_options_by_weight() {
+        let counts = count_selections(&mut WeightedSelector);
+
+        assert_eq!(counts[3], 0);
+        assert!((900..1100).contains(&counts[0]));
+        assert!((1800..2200).contains(&counts[1]));
+        assert!((6700..7300).contains(&counts[2]));
+    }
+
+    #[test]
+    fn it_samples_like_the_expanded_weights() {
+        let weights = vec![3, 1000, 1, 0, 250, 7];
+        let wave = Wave::from_indices(1, 1, weights.clone(), &[vec![0, 1, 2, 3, 5]]);
+
+        for seed in 0..200 {
+            // every pattern repeated by its weight, as chosen before the cumulative sampling
+            let expanded: Vec<usize> = wave
+                .pattern_indices(0)
+                .flat_map(|pi| std::iter::repeat_n(pi, weights[pi] as usize))
+                .collect();
+            let expected = expanded[Rng::with_seed(seed).usize(..expanded.len())];
+
+            let chosen = WeightedSelector.select_option(&wave, 0, &mut Rng::with_seed(seed));
+            assert_eq!(chosen, expected);
+        }
+    }
+
+    #[test]
+    fn it_samples_independently_of_the_wave_history() {
+        let weights = vec![5, 2, 9, 1, 4];
+        let wave = Wave::from_indices(2, 1, weights, &[vec![0, 2, 3], vec![]]);
+
+        // the same patterns reached by removing and restoring in a different order
+        let mut other_wave = Wave::new(2, 1, wave.weights.clone());
+        for pi in [4, 2, 1, 0, 3] {
+            other_wave.remove(0, pi);
+        }
+        for pi in [3, 2, 0] {
+            other_wave.insert(0, pi);
+        }
+
+        for seed in 0..100 {
+            assert_eq!(
+                WeightedSelector.select_option(&wave, 0, &mut Rng::with_seed(seed)),
+                WeightedSelector.select_option(&other_wave, 0, &mut Rng::with_seed(seed))
+            );
+        }
+    }
+
+    #[test]
+    fn it_selects_the_heaviest_option() {
+        let counts = count_selections(&mut HeaviestSelector);
+
+        assert_eq!(counts, vec![0, 0, 10000, 0]);
+    }
+
+    #[test]
+    fn it_selects_options_uniformly() {
+        let counts = count_selections(&mut UniformSelector);
+
+        assert_eq!(counts[3], 0);
+        for &count in &counts[..3] {
+            assert!((3100..3600).contains(&count));
+        }
+    }
+
+    #[test]
+    fn it_scales_the_weights_by_the_temperature() {
+        let counts = count_selections(&mut TemperatureSelector { temperature: 1.0 });
+        assert_eq!(counts[3], 0);
+        assert!((6700..7300).contains(&counts[2]));
+
+        // weights 1, 4 and 49
+        let counts = count_selections(&mut TemperatureSelector { temperature: 0.5 });
+        assert_eq!(counts[3], 0);
+        assert!((8800..9300).contains(&counts[2]));
+
+        let counts = count_selections(&mut TemperatureSelector {
+            temperature: 1000.0,
+        });
+        assert_eq!(counts[3], 0);
+        for &count in &counts[..3] {
+            assert!((3100..3600).contains(&count));
+        }
+    }
+
+    #[test]
+    fn it_selects_the_heaviest_option_at_low_temperatures() {
+        let counts = count_selections(&mut TemperatureSelector { temperature: 0.001 });
+        assert_eq!(counts, count_selections(&mut HeaviestSelector));
+
+        // 1000^1000 overflows unless the weights are scaled down first
+        let wave = Wave::from_indices(1, 1, vec![3, 1000, 500, 1, 250], &[vec![0, 1, 2, 4]]);
+        let mut rng = Rng::with_seed(0);
+        let heaviest = HeaviestSelector.select_option(&wave, 0, &mut rng);
+
+        for _ in 0..1000 {
+            let mut selector = TemperatureSelector { temperature: 0.001 };
+            assert_eq!(selector.select_option(&wave, 0, &mut rng), heaviest);
+        }
+    }
+}
@@ -1,47 +1,453 @@
-use std::path::Path;
+use fastrand::Rng;
+use log::info;
+use log::warn;
 
+use crate::error::Error;
+use crate::error::Result;
 use crate::model::args::Args;
+use crate::model::color_map::RawRgba;
 use crate::model::image::RawImage;
+use crate::model::option_selection::OptionSelection;
 use crate::model::pattern_propagator::PatternPropagator;
+use crate::model::sample::Sample;
+use crate::model::solver::Solver;
+use crate::model::trail::Trail;
+use crate::model::wave::Contradiction;
 use crate::model::wave::Wave;
 
+use super::backtrack::backtrack;
 use super::build_propagator::build_propagator;
 use super::extract_patterns::extract_patterns;
 use super::initialize_wave::initialize_wave;
-use super::load_image::load_image;
-use super::load_image::load_image_raw;
+use super::mask_samples::mask_transparent_pixels;
 use super::observe::observe;
 use super::propagate::propagate;
+use super::quantize_samples::quantize_samples;
+use super::select_cell::create_cell_selector;
+use super::select_cell::CellSelector;
+use super::select_option::create_option_selector;
+use super::select_option::OptionSelector;
 
-pub fn run<T: AsRef<Path>>(args: Args<T>) -> RawImage {
+/// Extracts the patterns from the `samples`, optionally after masking their transparent
+/// pixels and quantizing their colors, and builds the propagator for them.
+pub fn prepare(mut samples: Vec<Sample>, args: &Args) -> Result<PatternPropagator> {
+    validate_args(&samples, args)?;
+
+    if args.skip_transparent {
+        mask_transparent_pixels(&mut samples);
+    }
+
+    if let Some(palette_size) = args.palette_size {
+        quantize_samples(
+            &mut samples,
+            palette_size as usize,
+            args.pattern_width,
+            args.pattern_height,
+            args.periodic_input,
+        );
+    }
+
+    let pattern_data = extract_patterns(
+        &samples,
+        args.pattern_width,
+        args.pattern_height,
+        args.symmetry,
+        args.periodic_input,
+    )?;
+
+    if pattern_data.patterns.is_empty() {
+        return Err(Error::InvalidArguments(String::from(
+            "every window of the samples contains a masked pixel",
+        )));
+    }
+
+    Ok(build_propagator(pattern_data))
+}
+
+/// Generates an image, starting over with a fresh seed drawn from `rng` whenever an attempt fails.
+pub fn run(propagator: &PatternPropagator, args: &Args, rng: &mut Rng) -> Result<RawImage> {
+    let Args {
+        target_image_width,
+        target_image_height,
+        periodic_output,
+        ref anchored_edges,
+        cell_selection,
+        option_selection,
+        temperature,
+        max_attempts,
+        solver,
+        ..
+    } = *args;
+
+    for attempt in 1..=max_attempts {
+        let seed = rng.u64(..);
+        let mut attempt_rng = Rng::with_seed(seed);
+        info!("attempt {} of {} with seed {}", attempt, max_attempts, seed);
+
+        let mut wave = initialize_wave(
+            propagator,
+            target_image_width,
+            target_image_height,
+            periodic_output,
+            anchored_edges,
+            &mut attempt_rng,
+        )?;
+
+        let mut cell_selector = create_cell_selector(cell_selection);
+        let mut option_selector = create_option_selector(option_selection, temperature);
+        let mut trail = Trail::new(solver == Solver::Backtrack);
+
+        match collapse_wave(
+            &mut wave,
+            propagator,
+            cell_selector.as_mut(),
+            option_selector.as_mut(),
+            &mut trail,
+            &mut attempt_rng,
+        ) {
+            Ok(()) => return Ok(combine_observations(&wave, propagator)),
+            Err(Error::Contradiction(Contradiction { index })) => warn!(
+                "  contradiction at ({}, {}), restarting",
+                index as u32 % wave.width,
+                index as u32 / wave.width,
+            ),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(Error::AttemptsExhausted {
+        attempts: max_attempts,
+    })
+}
+
+fn validate_args(samples: &[Sample], args: &Args) -> Result<()> {
     let Args {
-        path,
         pattern_width,
         pattern_height,
         target_image_width,
         target_image_height,
-    } = args;
+        periodic_output,
+        ref anchored_edges,
+        palette_size,
+        option_selection,
+        temperature,
+        max_attempts,
+        ..
+    } = *args;
 
-    let image = load_image(path);
+    if pattern_width == 0 || pattern_height == 0 {
+        return Err(Error::InvalidArguments(format!(
+            "pattern size {}x{} must not be zero",
+            pattern_width, pattern_height
+        )));
+    }
 
-    let pattern_data = extract_patterns(image, pattern_width, pattern_height);
-    let pattern_propagator = build_propagator(pattern_data);
-    let mut wave = initialize_wave(&pattern_propagator, target_image_width, target_image_height);
+    if samples.is_empty() {
+        return Err(Error::InvalidArguments(String::from(
+            "at least one sample is required",
+        )));
+    }
+
+    for Sample {
+        image,
+        weight,
+        mask,
+    } in samples
+    {
+        if pattern_width > image.width || pattern_height > image.height {
+            return Err(Error::InvalidArguments(format!(
+                "pattern size {}x{} is larger than the sample size {}x{}",
+                pattern_width, pattern_height, image.width, image.height
+            )));
+        }
 
-    //for i in 0..10 {
-    //    if !observe(&mut wave, &pattern_propagator) {
-    //        break;
-    //    }
-    //    propagate();
-    //}
+        if let Some(mask) = mask {
+            if mask.width != image.width || mask.height != image.height {
+                return Err(Error::InvalidArguments(format!(
+                    "mask size {}x{} differs from the sample size {}x{}",
+                    mask.width, mask.height, image.width, image.height
+                )));
+            }
+        }
+
+        if *weight == 0 {
+            return Err(Error::InvalidArguments(String::from(
+                "sample weights must not be zero",
+            )));
+        }
+    }
+
+    if target_image_width == 0 || target_image_height == 0 {
+        return Err(Error::InvalidArguments(format!(
+            "target size {}x{} must not be zero",
+            target_image_width, target_image_height
+        )));
+    }
+
+    if !periodic_output
+        && (target_image_width < pattern_width || target_image_height < pattern_height)
+    {
+        return Err(Error::InvalidArguments(format!(
+            "target size {}x{} is smaller than the pattern size {}x{}",
+            target_image_width, target_image_height, pattern_width, pattern_height
+        )));
+    }
+
+    if let Some(palette_size) = palette_size {
+        if palette_size == 0 || palette_size > 256 {
+            return Err(Error::InvalidArguments(format!(
+                "palette size {} must be between 1 and 256",
+                palette_size
+            )));
+        }
+    }
+
+    if periodic_output && !anchored_edges.is_empty() {
+        return Err(Error::InvalidArguments(String::from(
+            "a periodic output has no edges to anchor",
+        )));
+    }
 
-    while observe(&mut wave, &pattern_propagator) {
-        propagate(&mut wave, &pattern_propagator);
+    if option_selection == OptionSelection::Temperature
+        && !(temperature > 0.0 && temperature.is_finite())
+    {
+        return Err(Error::InvalidArguments(format!(
+            "temperature {} must be positive",
+            temperature
+        )));
     }
 
-    combine_observations()
+    if max_attempts == 0 {
+        return Err(Error::InvalidArguments(String::from(
+            "at least one attempt is required",
+        )));
+    }
+
+    Ok(())
 }
 
-fn combine_observations() -> RawImage {
-    load_image_raw("data/flowers.png")
+/// Alternates between observing and propagating until every wave entry is collapsed.
+/// Contradictions are resolved by backtracking if the `trail` is enabled.
+fn collapse_wave(
+    wave: &mut Wave,
+    propagator: &PatternPropagator,
+    cell_selector: &mut dyn CellSelector,
+    option_selector: &mut dyn OptionSelector,
+    trail: &mut Trail,
+    rng: &mut Rng,
+) -> Result<()> {
+    while observe(wave, propagator, cell_selector, option_selector, trail, rng) {
+        if let Err(contradiction) = propagate(wave, propagator, trail) {
+            if !trail.enabled {
+                return Err(contradiction.into());
+            }
+
+            backtrack(wave, propagator, trail, contradiction)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Maps every collapsed wave entry to the top left color of its remaining pattern.
+/// If the wave is not periodic the last rows and columns of the image are filled with
+/// the remaining colors of the patterns at the bottom and right edges.
+/// The palette indices of the patterns are mapped back to RGBA through the color map.
+fn combine_observations(wave: &Wave, propagator: &PatternPropagator) -> RawImage {
+    let pattern_data = &propagator.pattern_data;
+    let (width, height) = if wave.periodic {
+        (wave.width, wave.height)
+    } else {
+        (
+            wave.width + pattern_data.pattern_width - 1,
+            wave.height + pattern_data.pattern_height - 1,
+        )
+    };
+    let mut raw = Vec::with_capacity((4 * width * height) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            let wave_x = x.min(wave.width - 1);
+            let wave_y = y.min(wave.height - 1);
+            let index = (wave_y * wave.width + wave_x) as usize;
+            let pixel_index = ((y - wave_y) * pattern_data.pattern_width + x - wave_x) as usize;
+
+            if let Some(pi) = wave.pattern_indices(index).next() {
+                let color_index = pattern_data.patterns[pi].pixels[pixel_index];
+                let RawRgba(r, g, b, a) = pattern_data.color_map.get_color(color_index);
+                raw.extend_from_slice(&[r, g, b, a]);
+            }
+        }
+    }
+
+    (width, height, raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::color_map::ColorMap;
+    use crate::model::edge::Edge;
+    use crate::model::image::Image;
+    use crate::model::pattern_data::Pattern;
+    use crate::model::pattern_data::PatternData;
+
+    use super::*;
+
+    fn create_pattern_with_color(color_index: u8) -> Pattern {
+        Pattern {
+            pixels: vec![color_index],
+            weight: 1,
+        }
+    }
+
+    fn create_color_map(colors: &[u32]) -> ColorMap {
+        let mut color_map = ColorMap::new();
+        for &color in colors {
+            color_map.get_or_insert_index(RawRgba::from(color));
+        }
+        color_map
+    }
+
+    #[test]
+    fn it_combines_observations_into_an_image() {
+        let propagator = PatternPropagator {
+            pattern_data: PatternData {
+                color_map: create_color_map(&[0xff000000, 0xff0000ff, 0x80332211]),
+                edge_patterns: Default::default(),
+                image_height: 0,
+                image_width: 0,
+                pattern_height: 1,
+                pattern_width: 1,
+                patterns: vec![
+                    create_pattern_with_color(0),
+                    create_pattern_with_color(1),
+                    create_pattern_with_color(2),
+                ],
+            },
+            compatibilities: Vec::new(),
+            supports: Vec::new(),
+            total_weight: 3,
+        };
+
+        let wave = Wave::from_indices(2, 2, vec![1; 3], &[vec![2], vec![0], vec![1], vec![2]]);
+
+        let (width, height, raw) = combine_observations(&wave, &propagator);
+
+        assert_eq!(width, 2);
+        assert_eq!(height, 2);
+        #[rustfmt::skip]
+        assert_eq!(raw, vec![
+            0x11, 0x22, 0x33, 0x80,
+            0x00, 0x00, 0x00, 0xff,
+            0xff, 0x00, 0x00, 0xff,
+            0x11, 0x22, 0x33, 0x80,
+        ]);
+    }
+
+    #[test]
+    fn it_fills_the_edges_with_the_remaining_pattern_colors() {
+        let propagator = PatternPropagator {
+            pattern_data: PatternData {
+                color_map: create_color_map(&[0, 1, 2, 3, 4, 5, 6]),
+                edge_patterns: Default::default(),
+                image_height: 0,
+                image_width: 0,
+                pattern_height: 2,
+                pattern_width: 2,
+                patterns: vec![
+                    Pattern {
+                        pixels: vec![1, 2, 3, 4],
+                        weight: 1,
+                    },
+                    Pattern {
+                        pixels: vec![2, 5, 4, 6],
+                        weight: 1,
+                    },
+                ],
+            },
+            compatibilities: Vec::new(),
+            supports: Vec::new(),
+            total_weight: 2,
+        };
+
+        let wave = Wave::from_indices(2, 1, vec![1; 2], &[vec![0], vec![1]]);
+
+        let (width, height, raw) = combine_observations(&wave, &propagator);
+
+        assert_eq!(width, 3);
+        assert_eq!(height, 2);
+        let colors: Vec<u8> = raw.chunks(4).map(|c| c[0]).collect();
+        assert_eq!(colors, vec![1, 2, 5, 3, 4, 6]);
+    }
+
+    #[test]
+    fn it_rejects_invalid_arguments() {
+        let image = Image {
+            width: 4,
+            height: 3,
+            data: vec![0; 12],
+        };
+        let samples = [Sample::new(image.clone()), Sample::with_weight(image, 3)];
+
+        assert!(validate_args(&samples, &Args::default()).is_ok());
+        assert!(validate_args(&[], &Args::default()).is_err());
+        assert!(validate_args(
+            &[Sample::with_weight(samples[0].image.clone(), 0)],
+            &Args::default()
+        )
+        .is_err());
+        assert!(validate_args(
+            &[Sample::new(samples[0].image.clone()).with_mask(Image {
+                width: 3,
+                height: 4,
+                data: vec![0; 12],
+            })],
+            &Args::default()
+        )
+        .is_err());
+
+        let invalid_args = [
+            Args {
+                pattern_width: 0,
+                ..Args::default()
+            },
+            Args {
+                pattern_height: 4,
+                ..Args::default()
+            },
+            Args {
+                target_image_height: 0,
+                ..Args::default()
+            },
+            Args {
+                target_image_width: 2,
+                ..Args::default()
+            },
+            Args {
+                periodic_output: true,
+                anchored_edges: vec![Edge::Bottom],
+                ..Args::default()
+            },
+            Args {
+                palette_size: Some(257),
+                ..Args::default()
+            },
+            Args {
+                option_selection: OptionSelection::Temperature,
+                temperature: 0.0,
+                ..Args::default()
+            },
+            Args {
+                max_attempts: 0,
+                ..Args::default()
+            },
+        ];
+
+        for args in &invalid_args {
+            assert!(matches!(
+                validate_args(&samples, args),
+                Err(Error::InvalidArguments(_))
+            ));
+        }
+    }
 }
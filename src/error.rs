@@ -0,0 +1,80 @@
+use std::fmt;
+use std::io;
+
+use image::ImageError;
+
+use crate::model::wave::Contradiction;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Decode(ImageError),
+    Encode(ImageError),
+    InvalidArguments(String),
+    TooManyColors,
+    Contradiction(Contradiction),
+    AttemptsExhausted { attempts: u32 },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Wraps an error of the image crate, keeping I/O errors apart from
+    /// errors about the image format or its content.
+    pub fn from_decoding(error: ImageError) -> Self {
+        match error {
+            ImageError::IoError(e) => Error::Io(e),
+            e => Error::Decode(e),
+        }
+    }
+
+    pub fn from_encoding(error: ImageError) -> Self {
+        match error {
+            ImageError::IoError(e) => Error::Io(e),
+            e => Error::Encode(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "i/o error: {}", e),
+            Error::Decode(e) => write!(f, "could not decode image: {}", e),
+            Error::Encode(e) => write!(f, "could not encode image: {}", e),
+            Error::InvalidArguments(message) => write!(f, "invalid arguments: {}", message),
+            Error::TooManyColors => write!(
+                f,
+                "the samples have more than 256 colors, try to quantize them"
+            ),
+            Error::Contradiction(Contradiction { index }) => {
+                write!(f, "contradiction at wave entry {}", index)
+            }
+            Error::AttemptsExhausted { attempts } => {
+                write!(f, "all {} attempts failed with a contradiction", attempts)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Decode(e) | Error::Encode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<Contradiction> for Error {
+    fn from(contradiction: Contradiction) -> Self {
+        Error::Contradiction(contradiction)
+    }
+}
@@ -0,0 +1,10 @@
+pub mod builder;
+pub mod controller;
+pub mod error;
+pub mod model;
+pub mod view;
+
+pub use builder::Wfc;
+pub use builder::WfcBuilder;
+pub use error::Error;
+pub use error::Result;
@@ -1,23 +1,74 @@
-use model::args::Args;
+use std::process;
 
-pub mod controller;
-pub mod model;
-pub mod view;
+use clap::Parser;
+use cli::Cli;
+use log::error;
+use log::info;
+use wfc_demo::controller::collect_stats::save_stats;
+use wfc_demo::controller::export_atlas::save_atlas;
+use wfc_demo::controller::save_image::save_image_raw;
+use wfc_demo::controller::Controller;
+use wfc_demo::model::generation::Generation;
+
+mod cli;
 
 fn main() {
-    fastrand::seed(7);
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug")).init();
-
-    let args = Args {
-        path: "data/flowers.png",
-        pattern_width: 3,
-        pattern_height: 3,
-        target_image_width: 10,
-        target_image_height: 10,
+    let cli = Cli::parse();
+    env_logger::Builder::new()
+        .filter_level(cli.log_level)
+        .init();
+
+    let wfc = match cli.to_builder().and_then(|builder| builder.build()) {
+        Ok(wfc) => wfc,
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Some(atlas) = &cli.atlas {
+        match save_atlas(atlas, wfc.pattern_data(), cli.atlas_scale) {
+            Ok(()) => info!("wrote pattern atlas {}", atlas.display()),
+            Err(e) => {
+                error!("could not write {}: {}", atlas.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(stats) = &cli.stats {
+        match save_stats(stats, &wfc.stats()) {
+            Ok(()) => info!("wrote pattern statistics {}", stats.display()),
+            Err(e) => {
+                error!("could not write {}: {}", stats.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if cli.no_generate {
+        return;
+    }
+
+    let Generation { image, seed } = match wfc.run() {
+        Ok(generation) => generation,
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
     };
+    info!("generated with seed {}", seed);
 
-    controller::wfc::run(args);
+    if cli.interactive {
+        term2d::run(Controller::new(image));
+        return;
+    }
 
-    //let controller = Controller::new();
-    //term2d::run(controller);
+    match save_image_raw(&cli.output, &image) {
+        Ok(()) => info!("wrote {}", cli.output.display()),
+        Err(e) => {
+            error!("could not write {}: {}", cli.output.display(), e);
+            process::exit(1);
+        }
+    }
 }
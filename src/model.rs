@@ -1,8 +1,17 @@
 pub mod args;
+pub mod cell_selection;
 pub mod color_map;
+pub mod edge;
+pub mod generation;
 pub mod image;
+pub mod option_selection;
 pub mod pattern_data;
 pub mod pattern_extractor;
 pub mod pattern_propagator;
+pub mod pattern_stats;
+pub mod sample;
+pub mod solver;
 pub mod state;
+pub mod symmetry;
+pub mod trail;
 pub mod wave;
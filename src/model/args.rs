@@ -1,9 +1,45 @@
-use std::path::Path;
+use super::cell_selection::CellSelection;
+use super::edge::Edge;
+use super::option_selection::OptionSelection;
+use super::solver::Solver;
+use super::symmetry::Symmetry;
 
-pub struct Args<T: AsRef<Path>> {
-    pub path: T,
+pub struct Args {
     pub pattern_width: u32,
     pub pattern_height: u32,
+    pub symmetry: Symmetry,
+    pub periodic_input: bool,
+    pub skip_transparent: bool,
+    pub palette_size: Option<u32>,
     pub target_image_width: u32,
     pub target_image_height: u32,
+    pub periodic_output: bool,
+    pub anchored_edges: Vec<Edge>,
+    pub cell_selection: CellSelection,
+    pub option_selection: OptionSelection,
+    pub temperature: f64,
+    pub max_attempts: u32,
+    pub solver: Solver,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            pattern_width: 3,
+            pattern_height: 3,
+            symmetry: Symmetry::None,
+            periodic_input: true,
+            skip_transparent: false,
+            palette_size: None,
+            target_image_width: 32,
+            target_image_height: 32,
+            periodic_output: false,
+            anchored_edges: Vec::new(),
+            cell_selection: CellSelection::Neighborhood,
+            option_selection: OptionSelection::Weighted,
+            temperature: 1.0,
+            max_attempts: 10,
+            solver: Solver::Restart,
+        }
+    }
 }
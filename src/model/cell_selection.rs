@@ -0,0 +1,18 @@
+use clap::ValueEnum;
+
+/// Which uncollapsed wave entry is observed next.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum CellSelection {
+    /// The entry with the lowest entropy of the whole wave.
+    MinEntropy,
+    /// The entry with the lowest entropy next to the last collapsed entry,
+    /// or of the whole wave if all of its neighbors are collapsed.
+    #[default]
+    Neighborhood,
+    /// The first uncollapsed entry row by row.
+    Scanline,
+    /// The entry with the fewest remaining patterns, ignoring their weights.
+    RemainingValues,
+    /// Any uncollapsed entry with equal probability.
+    Random,
+}
@@ -3,6 +3,16 @@ use std::collections::HashMap;
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct RawRgba(pub u8, pub u8, pub u8, pub u8);
 
+impl From<u32> for RawRgba {
+    /// Unpacks a color in the byte order `load_image` packs it.
+    fn from(color: u32) -> Self {
+        let [r, g, b, a] = color.to_le_bytes();
+        RawRgba(r, g, b, a)
+    }
+}
+
+/// A palette of at most 256 colors, `colors[i]` is the color with the palette index `i`.
+#[derive(Clone, Debug)]
 pub struct ColorMap {
     pub colors: [RawRgba; 256],
     pub indices: HashMap<RawRgba, u8>,
@@ -15,10 +25,43 @@ impl ColorMap {
             indices: HashMap::new(),
         }
     }
+
+    /// Number of colors in the palette.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Returns the palette index of the color, adding it to the palette if necessary.
+    /// Returns `None` if the palette is full.
+    pub fn get_or_insert_index(&mut self, color: RawRgba) -> Option<u8> {
+        if let Some(&index) = self.indices.get(&color) {
+            return Some(index);
+        }
+
+        let index = u8::try_from(self.len()).ok()?;
+        self.colors[index as usize] = color;
+        self.indices.insert(color, index);
+        Some(index)
+    }
+
+    pub fn get_color(&self, index: u8) -> RawRgba {
+        self.colors[index as usize]
+    }
 }
 
+impl Default for ColorMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An image whose pixels are indices into a `ColorMap`.
+#[derive(Clone, Debug)]
 pub struct CompressedImage {
-    pub color_map: ColorMap,
     pub data: Vec<u8>,
     pub height: u32,
     pub width: u32,
@@ -26,14 +69,45 @@ pub struct CompressedImage {
 
 impl CompressedImage {
     pub fn new(width: u32, height: u32) -> Self {
-        let color_map = ColorMap::new();
         let data = vec![0; (width * height) as usize];
 
         Self {
-            color_map,
             data,
             height,
             width,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_assigns_palette_indices_in_order_of_appearance() {
+        let mut color_map = ColorMap::new();
+        let red = RawRgba(255, 0, 0, 255);
+        let blue = RawRgba(0, 0, 255, 255);
+
+        assert_eq!(color_map.get_or_insert_index(red), Some(0));
+        assert_eq!(color_map.get_or_insert_index(blue), Some(1));
+        assert_eq!(color_map.get_or_insert_index(red), Some(0));
+        assert_eq!(color_map.len(), 2);
+        assert_eq!(color_map.get_color(1), blue);
+    }
+
+    #[test]
+    fn it_holds_at_most_256_colors() {
+        let mut color_map = ColorMap::new();
+
+        for i in 0..256 {
+            assert_eq!(
+                color_map.get_or_insert_index(RawRgba::from(i)),
+                Some(i as u8)
+            );
+        }
+
+        assert_eq!(color_map.get_or_insert_index(RawRgba::from(256)), None);
+        assert_eq!(color_map.get_or_insert_index(RawRgba::from(255)), Some(255));
+    }
+}
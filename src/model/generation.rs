@@ -0,0 +1,7 @@
+use super::image::RawImage;
+
+/// A generated image together with the seed that reproduces it.
+pub struct Generation {
+    pub image: RawImage,
+    pub seed: u64,
+}
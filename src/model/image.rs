@@ -1,5 +1,6 @@
 pub type RawImage = (u32, u32, Vec<u8>);
 
+#[derive(Clone, Debug)]
 pub struct Image {
     pub width: u32,
     pub height: u32,
@@ -0,0 +1,16 @@
+use clap::ValueEnum;
+
+/// How the pattern of an observed wave entry is chosen.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum OptionSelection {
+    /// Randomly with probabilities proportional to the pattern weights.
+    #[default]
+    Weighted,
+    /// The pattern with the highest weight, the lowest pattern index wins ties.
+    Heaviest,
+    /// Randomly with equal probabilities.
+    Uniform,
+    /// Randomly with probabilities proportional to `weight^(1 / temperature)`,
+    /// temperatures below 1 favor heavy patterns and above 1 flatten the distribution.
+    Temperature,
+}
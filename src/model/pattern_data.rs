@@ -1,4 +1,12 @@
+use super::color_map::ColorMap;
+use super::edge::Edge;
+
 pub struct PatternData {
+    /// The palette of the colors referenced by the pattern pixels.
+    pub color_map: ColorMap,
+    /// For each edge of the sample the indices of the patterns touching it,
+    /// see `get_edge_patterns`.
+    pub edge_patterns: [Vec<usize>; 4],
     pub image_height: u32,
     pub image_width: u32,
     pub pattern_height: u32,
@@ -8,47 +16,54 @@ pub struct PatternData {
 
 #[derive(Clone, Debug)]
 pub struct Pattern {
-    // TODO: rename to colors to match wording in PatternPixel
-    pub pixels: Vec<u32>,
+    /// Palette indices into `PatternData::color_map`.
+    pub pixels: Vec<u8>,
     pub weight: u32,
 }
 
 impl PatternData {
-    pub fn get_pixel_index(&self, pattern_index: usize, x: u32, y: u32) -> usize {
-        let p = pattern_index;
-        let x = x as usize;
-        let y = y as usize;
+    /// Returns the indices of the patterns which were extracted from a window lying
+    /// fully inside the sample and touching its `edge`.
+    pub fn get_edge_patterns(&self, edge: Edge) -> &[usize] {
+        &self.edge_patterns[edge as usize]
+    }
+
+    /// Number of offsets at which two patterns overlap, these range from
+    /// `-(pattern_width - 1)` to `pattern_width - 1` horizontally and likewise vertically.
+    pub fn get_offset_count(&self) -> usize {
         let w = self.pattern_width as usize;
         let h = self.pattern_height as usize;
 
-        let s1 = w;
-        let s2 = w * h;
+        (2 * w - 1) * (2 * h - 1)
+    }
+
+    /// Checks if two patterns overlap when one is placed at the offset (`dx`, `dy`) of the other.
+    pub fn is_overlapping(&self, dx: i32, dy: i32) -> bool {
+        let w = self.pattern_width as i32;
+        let h = self.pattern_height as i32;
 
-        p * s2 + y * s1 + x
+        dx > -w && dx < w && dy > -h && dy < h
     }
 
-    pub fn get_relationship_index(
-        &self,
-        pattern_index: usize,
-        x: u32,
-        y: u32,
-        u: u32,
-        v: u32,
-    ) -> usize {
+    /// Calculates the index into `PatternPropagator::compatibilities` for the pattern
+    /// `pattern_index` and the offset (`dx`, `dy`) of the compared pattern.
+    /// Returns `None` if the patterns don't overlap at that offset.
+    pub fn get_compatibility_index(&self, pattern_index: usize, dx: i32, dy: i32) -> Option<usize> {
+        if !self.is_overlapping(dx, dy) {
+            return None;
+        }
+
+        let w = self.pattern_width as i32;
+        let h = self.pattern_height as i32;
+
         let p = pattern_index;
-        let x = x as usize;
-        let y = y as usize;
-        let u = u as usize;
-        let v = v as usize;
-        let w = self.pattern_width as usize;
-        let h = self.pattern_height as usize;
+        let u = (dx + w - 1) as usize;
+        let v = (dy + h - 1) as usize;
 
-        let s1 = w;
-        let s2 = s1 * h;
-        let s3 = s2 * w;
-        let s4 = s3 * h;
+        let s1 = (2 * w - 1) as usize;
+        let s2 = self.get_offset_count();
 
-        p * s4 + y * s3 + x * s2 + v * s1 + u
+        Some(p * s2 + v * s1 + u)
     }
 }
 
@@ -57,12 +72,14 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_calculates_relationship_indices() {
+    fn it_calculates_compatibility_indices() {
         let w = 5;
         let h = 3;
         let p = 8;
 
         let pattern_data = PatternData {
+            color_map: ColorMap::new(),
+            edge_patterns: Default::default(),
             image_height: 0,
             image_width: 0,
             pattern_height: h,
@@ -72,21 +89,37 @@ mod tests {
 
         let mut indices = Vec::new();
         for i in 0..p {
-            for y in 0..h {
-                for x in 0..w {
-                    for v in 0..h {
-                        for u in 0..w {
-                            let index = pattern_data.get_relationship_index(i, x, y, u, v);
-                            indices.push(index);
-                        }
-                    }
+            for dy in -(h as i32 - 1)..h as i32 {
+                for dx in -(w as i32 - 1)..w as i32 {
+                    let index = pattern_data.get_compatibility_index(i, dx, dy);
+                    indices.push(index.unwrap());
                 }
             }
         }
 
-        assert_eq!(indices.len(), p * (w * h * w * h) as usize);
-        for i in 0..indices.len() {
-            assert_eq!(indices[i], i);
+        assert_eq!(pattern_data.get_offset_count(), 45);
+        assert_eq!(indices.len(), p * pattern_data.get_offset_count());
+        for (i, &index) in indices.iter().enumerate() {
+            assert_eq!(index, i);
         }
     }
+
+    #[test]
+    fn it_does_not_calculate_compatibility_indices_without_overlap() {
+        let pattern_data = PatternData {
+            color_map: ColorMap::new(),
+            edge_patterns: Default::default(),
+            image_height: 0,
+            image_width: 0,
+            pattern_height: 3,
+            pattern_width: 5,
+            patterns: Vec::new(),
+        };
+
+        assert_eq!(pattern_data.get_compatibility_index(0, 5, 0), None);
+        assert_eq!(pattern_data.get_compatibility_index(0, -5, 0), None);
+        assert_eq!(pattern_data.get_compatibility_index(0, 0, 3), None);
+        assert_eq!(pattern_data.get_compatibility_index(0, 0, -3), None);
+        assert!(pattern_data.get_compatibility_index(0, 4, -2).is_some());
+    }
 }
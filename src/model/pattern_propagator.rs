@@ -1,17 +1,59 @@
 use super::pattern_data::PatternData;
 
-#[derive(Clone)]
-pub struct PatternPixel {
-    pub color: u32,
-    pub colors: Vec<u32>,
-    pub relationships: Vec<bool>,
-    pub weight: u32,
-    pub x: u32,
-    pub y: u32,
-}
+/// Offsets of the 8 neighbors of a wave entry, the opposite of direction `d` is `7 - d`.
+pub const DIRECTIONS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
 
 pub struct PatternPropagator {
     pub pattern_data: PatternData,
-    pub pattern_pixels: Vec<PatternPixel>,
+    /// For each pattern and each offset inside the pattern size the indices of all
+    /// patterns which may be placed at that offset, see `PatternData::get_compatibility_index`.
+    pub compatibilities: Vec<Vec<usize>>,
+    /// For each pattern and direction the number of patterns supporting it from a neighbor
+    /// in that direction, indexed by `pattern_index * DIRECTIONS.len() + direction`.
+    pub supports: Vec<u32>,
     pub total_weight: u32,
 }
+
+impl PatternPropagator {
+    /// Returns the patterns which may be placed at the offset (`dx`, `dy`) relative to the
+    /// pattern `pattern_index`, or `None` if the patterns don't overlap at that offset,
+    /// in which case every pattern is allowed.
+    pub fn get_compatible_patterns(
+        &self,
+        pattern_index: usize,
+        dx: i32,
+        dy: i32,
+    ) -> Option<&[usize]> {
+        self.pattern_data
+            .get_compatibility_index(pattern_index, dx, dy)
+            .map(|i| self.compatibilities[i].as_slice())
+    }
+
+    /// Returns the patterns which may be placed at the neighbor in `direction`,
+    /// or `None` if the neighbor does not overlap.
+    pub fn get_neighbor_patterns(
+        &self,
+        pattern_index: usize,
+        direction: usize,
+    ) -> Option<&[usize]> {
+        let (dx, dy) = DIRECTIONS[direction];
+        self.get_compatible_patterns(pattern_index, dx, dy)
+    }
+
+    pub fn get_weights(&self) -> Vec<u32> {
+        self.pattern_data
+            .patterns
+            .iter()
+            .map(|pattern| pattern.weight)
+            .collect()
+    }
+}
@@ -0,0 +1,47 @@
+use serde::Serialize;
+
+/// Summary of the extracted patterns and their propagator, serialized as the JSON report.
+#[derive(Debug, Serialize)]
+pub struct PatternStats {
+    pub pattern_count: usize,
+    pub pattern_width: u32,
+    pub pattern_height: u32,
+    pub color_count: usize,
+    pub weights: WeightDistribution,
+    /// Shannon entropy of the pattern weights in bits.
+    pub entropy: f64,
+    /// Entropy of the same number of equally weighted patterns, i.e. `log2(pattern_count)`.
+    pub max_entropy: f64,
+    pub offsets: Vec<OffsetDensity>,
+    pub propagator_bytes: usize,
+    pub unsupported_patterns: Vec<UnsupportedPattern>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WeightDistribution {
+    pub total: u32,
+    pub min: u32,
+    pub max: u32,
+    pub mean: f64,
+    pub median: f64,
+    /// All pattern weights from the heaviest to the lightest.
+    pub sorted: Vec<u32>,
+}
+
+/// Fraction of pattern pairs which are compatible at the offset (`dx`, `dy`).
+#[derive(Debug, Serialize)]
+pub struct OffsetDensity {
+    pub dx: i32,
+    pub dy: i32,
+    pub compatibilities: usize,
+    pub density: f64,
+}
+
+/// A pattern without any compatible neighbor in the listed directions,
+/// it is removed from the wave wherever such a neighbor exists.
+#[derive(Debug, Serialize)]
+pub struct UnsupportedPattern {
+    pub pattern: usize,
+    pub weight: u32,
+    pub directions: Vec<(i32, i32)>,
+}
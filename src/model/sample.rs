@@ -0,0 +1,39 @@
+use super::image::Image;
+
+/// A sample image whose pattern weights are multiplied by `weight`.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub image: Image,
+    pub weight: u32,
+    /// An image of the same size as the sample marking the pixels which no pattern may
+    /// contain, see `is_masked`.
+    pub mask: Option<Image>,
+}
+
+impl Sample {
+    pub fn new(image: Image) -> Self {
+        Self::with_weight(image, 1)
+    }
+
+    pub fn with_weight(image: Image, weight: u32) -> Self {
+        Self {
+            image,
+            weight,
+            mask: None,
+        }
+    }
+
+    pub fn with_mask(mut self, mask: Image) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Checks if the sample pixel at `index` is marked in the mask,
+    /// i.e. the mask pixel is neither black nor fully transparent.
+    pub fn is_masked(&self, index: usize) -> bool {
+        self.mask.as_ref().is_some_and(|mask| {
+            let pixel = mask.data[index];
+            pixel >> 24 != 0 && pixel & 0x00ff_ffff != 0
+        })
+    }
+}
@@ -0,0 +1,10 @@
+use clap::ValueEnum;
+
+/// How a run reacts to a contradiction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Solver {
+    /// Start over from a fresh wave with a new seed.
+    Restart,
+    /// Undo the wave changes back to the last decision and try another choice.
+    Backtrack,
+}
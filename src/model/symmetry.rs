@@ -0,0 +1,36 @@
+use clap::ValueEnum;
+
+/// Which transformed variants of the sample are used to extract patterns.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Symmetry {
+    /// Only the sample in its original orientation.
+    None,
+    /// The sample and its horizontal reflection.
+    Reflection,
+    /// The sample rotated by 0, 90, 180 and 270 degrees.
+    Rotation,
+    /// All 8 rotations and reflections of the sample.
+    Dihedral,
+}
+
+impl Symmetry {
+    /// Returns the variants as pairs of clockwise quarter turns and whether the sample
+    /// is reflected horizontally before rotating it.
+    pub fn get_variants(self) -> &'static [(u32, bool)] {
+        match self {
+            Symmetry::None => &[(0, false)],
+            Symmetry::Reflection => &[(0, false), (0, true)],
+            Symmetry::Rotation => &[(0, false), (1, false), (2, false), (3, false)],
+            Symmetry::Dihedral => &[
+                (0, false),
+                (1, false),
+                (2, false),
+                (3, false),
+                (0, true),
+                (1, true),
+                (2, true),
+                (3, true),
+            ],
+        }
+    }
+}
@@ -0,0 +1,31 @@
+/// A single change of the wave, recorded so that it can be undone.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TrailEntry {
+    /// `pattern_index` was chosen as the only pattern of the wave entry at `index`.
+    Decision { index: usize, pattern_index: usize },
+    /// `pattern_index` was removed from the wave entry at `index`.
+    Removal { index: usize, pattern_index: usize },
+}
+
+/// Records the changes to the wave in the order they were made.
+/// A disabled trail ignores all entries, which is used when restarting
+/// instead of backtracking.
+pub struct Trail {
+    pub enabled: bool,
+    pub entries: Vec<TrailEntry>,
+}
+
+impl Trail {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, entry: TrailEntry) {
+        if self.enabled {
+            self.entries.push(entry);
+        }
+    }
+}
@@ -1,6 +1,459 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use fastrand::Rng;
+
+use super::pattern_propagator::DIRECTIONS;
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// Upper bound of the random noise added to the entropies to break ties, small enough
+/// not to reorder entries whose entropies really differ.
+const ENTROPY_NOISE: f64 = 1e-6;
+
+/// Stores for every wave entry the set of pattern indices which are still possible.
+/// Each entry is a fixed-width bitset inside `bits`, the number of set bits and the sum
+/// of their weights are cached in `counts` and `weight_sums`.
+///
+/// The entropy of each entry is cached in `entropies` and updated on every removal and
+/// insertion from the sum of `w * ln(w)` of its patterns, see `get_entropy`. Entries with
+/// more than one pattern are kept in a min-heap ordered by entropy plus a small random noise,
+/// outdated heap elements are skipped when the heap is read, see `find_lowest_entropy`.
+///
+/// `supports` holds for every entry, pattern and direction the number of patterns of the
+/// neighbor in that direction which support the pattern, it is maintained by `propagate`.
+/// Patterns whose support dropped to zero are queued in `unsupported` until they are removed.
+/// A `periodic` wave wraps around its edges.
+#[derive(Clone)]
 pub struct Wave {
     pub width: u32,
     pub height: u32,
-    pub indices: Vec<Vec<usize>>,
+    pub periodic: bool,
+    pub bits: Vec<u64>,
+    pub counts: Vec<usize>,
+    pub weight_sums: Vec<u32>,
+    pub weight_log_weight_sums: Vec<f64>,
+    pub entropies: Vec<f64>,
+    pub weights: Vec<u32>,
+    pub weight_log_weights: Vec<f64>,
+    pub words_per_entry: usize,
+    pub supports: Vec<u32>,
+    pub unsupported: Vec<(usize, usize)>,
     pub last_index_collapsed: usize,
+    entropy_noise: Vec<f64>,
+    entropy_heap: BinaryHeap<EntropyHeapElement>,
+}
+
+impl Wave {
+    /// Creates a wave where every entry contains all patterns,
+    /// `weights` holds the weight of each pattern.
+    pub fn new(width: u32, height: u32, weights: Vec<u32>) -> Self {
+        let size = (width * height) as usize;
+        let option_count = weights.len();
+        let words_per_entry = option_count.div_ceil(WORD_BITS);
+
+        let mut entry = vec![u64::MAX; words_per_entry];
+        let remaining_bits = option_count % WORD_BITS;
+        if remaining_bits > 0 {
+            entry[words_per_entry - 1] = (1 << remaining_bits) - 1;
+        }
+
+        let weight_sum = weights.iter().sum();
+        let weight_log_weights: Vec<f64> = weights
+            .iter()
+            .map(|&w| w as f64 * (w as f64).ln())
+            .collect();
+        let weight_log_weight_sum = weight_log_weights.iter().sum();
+        let entropy = calculate_entropy(weight_sum, weight_log_weight_sum);
+
+        let entropy_heap = if option_count > 1 {
+            (0..size)
+                .map(|index| EntropyHeapElement { entropy, index })
+                .collect()
+        } else {
+            BinaryHeap::new()
+        };
+
+        Self {
+            width,
+            height,
+            periodic: false,
+            bits: entry.repeat(size),
+            counts: vec![option_count; size],
+            weight_sums: vec![weight_sum; size],
+            weight_log_weight_sums: vec![weight_log_weight_sum; size],
+            entropies: vec![entropy; size],
+            weights,
+            weight_log_weights,
+            words_per_entry,
+            supports: Vec::new(),
+            unsupported: Vec::new(),
+            last_index_collapsed: 0,
+            entropy_noise: vec![0.0; size],
+            entropy_heap,
+        }
+    }
+
+    /// Creates a wave where the entry at index `i` contains exactly the patterns `indices[i]`.
+    pub fn from_indices(
+        width: u32,
+        height: u32,
+        weights: Vec<u32>,
+        indices: &[Vec<usize>],
+    ) -> Self {
+        let mut wave = Self::new(width, height, weights);
+
+        for (index, pattern_indices) in indices.iter().enumerate() {
+            for pattern_index in 0..wave.weights.len() {
+                if !pattern_indices.contains(&pattern_index) {
+                    wave.remove(index, pattern_index);
+                }
+            }
+        }
+
+        wave
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    pub fn contains(&self, index: usize, pattern_index: usize) -> bool {
+        let (word, mask) = self.locate(index, pattern_index);
+        self.bits[word] & mask != 0
+    }
+
+    /// Removes the pattern from the wave entry, returns `false` if it was not present.
+    pub fn remove(&mut self, index: usize, pattern_index: usize) -> bool {
+        let (word, mask) = self.locate(index, pattern_index);
+        if self.bits[word] & mask == 0 {
+            return false;
+        }
+
+        self.bits[word] &= !mask;
+        self.counts[index] -= 1;
+        self.weight_sums[index] -= self.weights[pattern_index];
+        self.weight_log_weight_sums[index] -= self.weight_log_weights[pattern_index];
+        self.update_entropy(index);
+        true
+    }
+
+    /// Adds the pattern to the wave entry, returns `false` if it was already present.
+    pub fn insert(&mut self, index: usize, pattern_index: usize) -> bool {
+        let (word, mask) = self.locate(index, pattern_index);
+        if self.bits[word] & mask != 0 {
+            return false;
+        }
+
+        self.bits[word] |= mask;
+        self.counts[index] += 1;
+        self.weight_sums[index] += self.weights[pattern_index];
+        self.weight_log_weight_sums[index] += self.weight_log_weights[pattern_index];
+        self.update_entropy(index);
+        true
+    }
+
+    /// Returns the shannon entropy of the patterns of the entry, whose probabilities are their
+    /// weights divided by the weight sum of the entry. It is 0 for a collapsed entry.
+    pub fn get_entropy(&self, index: usize) -> f64 {
+        self.entropies[index]
+    }
+
+    /// Returns the entropy of the entry plus its tie breaking noise, see `add_entropy_noise`.
+    pub fn get_noisy_entropy(&self, index: usize) -> f64 {
+        self.entropies[index] + self.entropy_noise[index]
+    }
+
+    /// Adds a small random value to the entropy of every entry when comparing entropies,
+    /// so that ties between entries are broken randomly instead of by their position.
+    pub fn add_entropy_noise(&mut self, rng: &mut Rng) {
+        for noise in self.entropy_noise.iter_mut() {
+            *noise = ENTROPY_NOISE * rng.f64();
+        }
+
+        self.entropy_heap = (0..self.len())
+            .filter(|&index| self.counts[index] > 1)
+            .map(|index| EntropyHeapElement {
+                entropy: self.get_noisy_entropy(index),
+                index,
+            })
+            .collect();
+    }
+
+    /// Returns the entry with more than one pattern and the lowest noisy entropy,
+    /// the lowest index wins if several entries have the same one.
+    /// Drops the outdated heap elements on the way.
+    pub fn find_lowest_entropy(&mut self) -> Option<usize> {
+        while let Some(&EntropyHeapElement { entropy, index }) = self.entropy_heap.peek() {
+            if self.counts[index] > 1 && self.get_noisy_entropy(index) == entropy {
+                return Some(index);
+            }
+            self.entropy_heap.pop();
+        }
+
+        None
+    }
+
+    /// Iterates over the pattern indices of the wave entry in ascending order.
+    pub fn pattern_indices(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let start = index * self.words_per_entry;
+        let words = &self.bits[start..start + self.words_per_entry];
+
+        words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(i * WORD_BITS + bit)
+            })
+        })
+    }
+
+    /// Returns the index of the neighbor in `direction`, see `DIRECTIONS`.
+    /// Returns `None` if the neighbor is outside of a wave which is not periodic.
+    pub fn get_neighbor(&self, index: usize, direction: usize) -> Option<usize> {
+        let width = self.width as i32;
+        let height = self.height as i32;
+        let (dx, dy) = DIRECTIONS[direction];
+
+        let mut x = (index as i32 % width) + dx;
+        let mut y = (index as i32 / width) + dy;
+
+        if self.periodic {
+            x = x.rem_euclid(width);
+            y = y.rem_euclid(height);
+        } else if x < 0 || x >= width || y < 0 || y >= height {
+            return None;
+        }
+
+        Some((y * width + x) as usize)
+    }
+
+    /// Returns the position in `supports` of the pattern of the wave entry in `direction`.
+    pub fn get_support_index(&self, index: usize, pattern_index: usize, direction: usize) -> usize {
+        (index * self.weights.len() + pattern_index) * DIRECTIONS.len() + direction
+    }
+
+    /// Recalculates the cached entropy of the entry and queues it again if it has more
+    /// than one pattern left, the previous heap element of the entry becomes outdated.
+    fn update_entropy(&mut self, index: usize) {
+        self.entropies[index] =
+            calculate_entropy(self.weight_sums[index], self.weight_log_weight_sums[index]);
+
+        if self.counts[index] > 1 {
+            self.entropy_heap.push(EntropyHeapElement {
+                entropy: self.get_noisy_entropy(index),
+                index,
+            });
+        }
+    }
+
+    fn locate(&self, index: usize, pattern_index: usize) -> (usize, u64) {
+        let word = index * self.words_per_entry + pattern_index / WORD_BITS;
+        let mask = 1 << (pattern_index % WORD_BITS);
+        (word, mask)
+    }
+}
+
+/// Calculates the shannon entropy `-sum(p * ln(p))` of patterns with `p = w / weight_sum`
+/// from their weight sum and the sum of `w * ln(w)`, which equals
+/// `ln(weight_sum) - sum(w * ln(w)) / weight_sum`.
+fn calculate_entropy(weight_sum: u32, weight_log_weight_sum: f64) -> f64 {
+    if weight_sum == 0 {
+        return 0.0;
+    }
+
+    let weight_sum = weight_sum as f64;
+    (weight_sum.ln() - weight_log_weight_sum / weight_sum).max(0.0)
+}
+
+/// An entry of the entropy heap, ordered so that the lowest entropy and then the lowest
+/// index is at the top of the max-heap.
+#[derive(Clone, Copy, Debug)]
+struct EntropyHeapElement {
+    entropy: f64,
+    index: usize,
+}
+
+impl Ord for EntropyHeapElement {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .entropy
+            .total_cmp(&self.entropy)
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for EntropyHeapElement {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for EntropyHeapElement {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for EntropyHeapElement {}
+
+/// Signals that the wave entry at `index` ran out of patterns.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Contradiction {
+    pub index: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn it_initializes_all_entries_with_all_patterns() {
+        let wave = Wave::new(3, 2, vec![1; 70]);
+
+        assert_eq!(wave.len(), 6);
+        assert_eq!(wave.words_per_entry, 2);
+        for index in 0..wave.len() {
+            assert_eq!(wave.counts[index], 70);
+            assert_eq!(wave.weight_sums[index], 70);
+            assert_eq!(wave.pattern_indices(index).count(), 70);
+            assert!(wave.contains(index, 69));
+        }
+    }
+
+    #[test]
+    fn it_removes_and_inserts_patterns() {
+        let mut wave = Wave::new(2, 1, vec![1, 2, 3, 4, 5]);
+
+        assert!(wave.remove(1, 3));
+        assert!(!wave.remove(1, 3));
+        assert!(wave.remove(1, 0));
+        assert_eq!(wave.pattern_indices(1).collect::<Vec<_>>(), vec![1, 2, 4]);
+        assert_eq!(wave.counts[1], 3);
+        assert_eq!(wave.weight_sums[1], 10);
+
+        assert!(wave.insert(1, 3));
+        assert!(!wave.insert(1, 3));
+        assert_eq!(
+            wave.pattern_indices(1).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert_eq!(wave.counts[1], 4);
+        assert_eq!(wave.weight_sums[1], 14);
+
+        assert_eq!(wave.counts[0], 5);
+    }
+
+    #[test]
+    fn it_creates_a_wave_from_indices() {
+        let wave = Wave::from_indices(3, 1, vec![1; 100], &[vec![0], vec![99, 64, 3], vec![]]);
+
+        assert_eq!(wave.pattern_indices(0).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(wave.pattern_indices(1).collect::<Vec<_>>(), vec![3, 64, 99]);
+        assert_eq!(wave.pattern_indices(2).count(), 0);
+        assert_eq!(wave.counts, vec![1, 3, 0]);
+    }
+
+    #[test]
+    fn it_caches_the_entropy_of_each_entry() {
+        let mut wave = Wave::new(2, 1, vec![1, 2, 3, 4]);
+        let initial_entropy = wave.entropies[0];
+        let expected: f64 = [1.0f64, 2.0, 3.0, 4.0]
+            .iter()
+            .map(|w| -(w / 10.0) * (w / 10.0).ln())
+            .sum();
+        assert!((initial_entropy - expected).abs() < 1e-9);
+
+        // the probabilities are relative to the remaining weights 1 and 3
+        wave.remove(0, 3);
+        wave.remove(0, 1);
+        let expected = -(0.25f64 * 0.25f64.ln()) - 0.75 * 0.75f64.ln();
+        assert!((wave.get_entropy(0) - expected).abs() < 1e-9);
+
+        // a collapsed entry has no entropy
+        wave.remove(1, 0);
+        wave.remove(1, 1);
+        wave.remove(1, 2);
+        assert_eq!(wave.get_entropy(1), 0.0);
+
+        wave.insert(0, 1);
+        wave.insert(0, 3);
+        assert!((wave.entropies[0] - initial_entropy).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_breaks_entropy_ties_randomly() {
+        let mut first_indices = HashSet::new();
+
+        for seed in 0..20 {
+            let mut wave = Wave::new(4, 1, vec![1, 1]);
+            wave.add_entropy_noise(&mut Rng::with_seed(seed));
+            first_indices.insert(wave.find_lowest_entropy().unwrap());
+
+            // the noise never outweighs a real difference
+            let mut wave = Wave::from_indices(4, 1, vec![1, 1, 1], &vec![vec![0, 1, 2]; 4]);
+            wave.remove(3, 0);
+            wave.add_entropy_noise(&mut Rng::with_seed(seed));
+            assert_eq!(wave.find_lowest_entropy(), Some(3));
+        }
+
+        assert!(first_indices.len() > 1);
+    }
+
+    #[test]
+    fn it_finds_the_entry_with_the_lowest_entropy() {
+        let mut wave = Wave::from_indices(
+            4,
+            1,
+            vec![1, 1, 1, 1],
+            &[vec![0, 1, 2], vec![0], vec![1, 2], vec![0, 3]],
+        );
+        assert_eq!(wave.find_lowest_entropy(), Some(2));
+
+        // collapsing the entry outdates its heap element
+        wave.remove(2, 1);
+        assert_eq!(wave.find_lowest_entropy(), Some(3));
+
+        // undoing the removal queues it again
+        wave.insert(2, 1);
+        assert_eq!(wave.find_lowest_entropy(), Some(2));
+
+        wave.remove(0, 0);
+        assert_eq!(wave.find_lowest_entropy(), Some(0));
+
+        for index in [0, 2, 3] {
+            wave.remove(index, 1);
+            wave.remove(index, 3);
+        }
+        assert_eq!(wave.find_lowest_entropy(), None);
+    }
+
+    #[test]
+    fn it_finds_neighbors() {
+        let mut wave = Wave::new(3, 2, vec![1]);
+        let top_left = 0;
+        let right = 4;
+        let bottom_right = 7;
+
+        assert_eq!(wave.get_neighbor(4, top_left), Some(0));
+        assert_eq!(wave.get_neighbor(4, right), Some(5));
+        assert_eq!(wave.get_neighbor(0, top_left), None);
+        assert_eq!(wave.get_neighbor(5, right), None);
+        assert_eq!(wave.get_neighbor(2, bottom_right), None);
+
+        wave.periodic = true;
+        assert_eq!(wave.get_neighbor(0, top_left), Some(5));
+        assert_eq!(wave.get_neighbor(5, right), Some(3));
+        assert_eq!(wave.get_neighbor(2, bottom_right), Some(3));
+    }
 }
@@ -1,5 +1,5 @@
-const CURSOR_UP_LEFT: &'static str = "\x1b[1F";
-const ERASE_TO_EOL: &'static str = "\x1b[0K";
+const CURSOR_UP_LEFT: &str = "\x1b[1F";
+const ERASE_TO_EOL: &str = "\x1b[0K";
 
 pub fn start_progress_bar() {
     println!();
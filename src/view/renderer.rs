@@ -34,3 +34,9 @@ impl Renderer {
         self.canvas.display();
     }
 }
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}